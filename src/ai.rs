@@ -0,0 +1,344 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use serde::Deserialize;
+
+use std::f32::consts;
+
+use crate::{nearest_player, random_in, Player, Ship, SteeringWheel, INPUT_STEP};
+use crate::archetypes::AiProfile;
+use crate::config::{ArenaBounds, GameConfig};
+use crate::sailing::{SailState, Sails};
+use crate::waves::WaveState;
+
+// How hard the wheel is turned for each radian off course
+const STEERING_GAIN: f32 = 6.0;
+// Radians per second an enemy helmsman can spin the wheel
+pub const HELM_RATE: f32 = 20.0;
+const PATROL_RADIUS: f32 = 12.0;
+// Close enough to a patrol point to head for the next one
+const WAYPOINT_REACHED: f32 = 4.0;
+// Allies within this distance count as together
+const REGROUP_DISTANCE: f32 = 15.0;
+// Ships closer than this push each other apart
+const SEPARATION_RADIUS: f32 = 8.0;
+const SEPARATION_WEIGHT: f32 = 1.5;
+// How far ahead rays look for something to run into, and how widely the
+// side rays fan out
+const LOOKAHEAD: f32 = 12.0;
+const WHISKER_ANGLE: f32 = 0.5;
+const AVOIDANCE_WEIGHT: f32 = 2.0;
+// Distance from the arena edge at which enemies start turning back in
+const ARENA_MARGIN: f32 = 8.0;
+const FORMATION_SPACING: f32 = 8.0;
+
+/// How a wave's fleet lines up behind its flagship
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Deserialize)]
+pub enum Formation {
+    /// Single file, each ship in the wake of the one ahead
+    LineAhead,
+    /// A V with the flagship at the point
+    Wedge
+}
+
+impl Formation {
+    /// Where the nth ship after the flagship sails, relative to the flagship
+    fn slot(&self, index: usize) -> Vec3 {
+        // behind the flagship is +z in its own frame
+        match self {
+            Formation::LineAhead => Vec3::new(0.0, 0.0, index as f32 * FORMATION_SPACING),
+            Formation::Wedge => {
+                let rank = index.div_ceil(2) as f32;
+                let side = if index % 2 == 1 { -1.0 } else { 1.0 };
+                Vec3::new(side * rank, 0.0, rank) * FORMATION_SPACING
+            }
+        }
+    }
+}
+
+/// What an enemy is currently trying to do
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum Behaviour {
+    /// Sail a loop around home until a player comes into sight
+    Patrol,
+    /// Close in on the nearest player
+    Approach,
+    /// Circle the nearest player to keep them off one side, where the guns are
+    Broadside,
+    /// Run from the nearest player
+    Flee,
+    /// Head back to the other enemies
+    Regroup
+}
+
+impl Behaviour {
+    /// Whether a follower in formation keeps station on the flagship. Ships
+    /// that have a player to fight or run from break off on their own.
+    fn keeps_station(&self) -> bool {
+        matches!(self, Behaviour::Patrol | Behaviour::Regroup)
+    }
+}
+
+/// Per-enemy tendencies, so enemies in the same wave don't all act alike
+#[derive(Clone, Copy, Debug)]
+pub struct Personality {
+    /// How far away a player can be noticed from
+    pub sight_range: f32,
+    /// Distance kept from a player while circling them
+    pub broadside_range: f32,
+    /// Fraction of full health below which the enemy runs
+    pub flee_health: f32
+}
+
+impl Personality {
+    /// Rolls a personality from an archetype's ranges
+    pub fn random(profile: &AiProfile) -> Self {
+        Personality {
+            sight_range: random_in(profile.sight_range),
+            broadside_range: random_in(profile.broadside_range),
+            flee_health: random_in(profile.flee_health)
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct EnemyAi {
+    /// Order the ship was launched in, so the longest serving leads the fleet
+    launched: u64,
+    pub behaviour: Behaviour,
+    personality: Personality,
+    max_health: i32,
+    /// Centre of the patrol loop
+    home: Vec3,
+    /// Angle around home of the patrol point being sailed to
+    patrol_angle: f32
+}
+
+impl EnemyAi {
+    pub fn new(launched: u64, home: Vec3, max_health: i32, personality: Personality) -> Self {
+        EnemyAi {
+            launched,
+            behaviour: Behaviour::Patrol,
+            personality,
+            max_health,
+            home,
+            patrol_angle: 0.0
+        }
+    }
+
+    fn patrol_point(&self) -> Vec3 {
+        self.home + Quat::from_rotation_y(self.patrol_angle) * Vec3::X * PATROL_RADIUS
+    }
+
+    /// Picks a behaviour from the enemy's surroundings
+    fn decide(&self, health: i32, to_player: Option<Vec3>, apart: bool) -> Behaviour {
+        let low_health = (health as f32) < self.max_health as f32 * self.personality.flee_health;
+        let player_distance = to_player
+            .map(|to_player| to_player.length())
+            .filter(|distance| *distance <= self.personality.sight_range);
+        match player_distance {
+            Some(_) if low_health => Behaviour::Flee,
+            Some(distance) if distance > self.personality.broadside_range * 1.5 => Behaviour::Approach,
+            Some(_) => Behaviour::Broadside,
+            None if low_health || apart => Behaviour::Regroup,
+            None => Behaviour::Patrol
+        }
+    }
+}
+
+/// Turns the wheel, by at most `max_delta`, to bring the ship round onto `direction`
+pub fn steer_along(wheel: &mut SteeringWheel, t: &Transform, direction: Vec3, max_delta: f32) {
+    let angle = t.forward().angle_between(direction);
+    if direction.dot(t.right()) < 0.0 {
+        wheel.turn_towards(angle * STEERING_GAIN, max_delta);
+    } else {
+        wheel.turn_towards(-angle * STEERING_GAIN, max_delta);
+    }
+}
+
+/// Push away from any ship that is too close, stronger the closer it is
+fn separation(position: Vec3, others: impl Iterator<Item = Vec3>) -> Vec3 {
+    others.fold(Vec3::ZERO, |push, other| {
+        let away = position - other;
+        let distance = away.length();
+        // skips the ship itself, which is at zero distance
+        if distance > f32::EPSILON && distance < SEPARATION_RADIUS {
+            push + away / distance * (1.0 - distance / SEPARATION_RADIUS)
+        } else {
+            push
+        }
+    })
+}
+
+/// Feel ahead with rays and turn away from whatever they hit
+fn obstacle_avoidance(
+    query_pipeline: &QueryPipeline,
+    collider_set: &QueryPipelineColliderComponentsSet,
+    ship: Entity,
+    t: &Transform
+) -> Vec3 {
+    let own_collider: ColliderHandle = ship.handle();
+    let filter = |handle: ColliderHandle| handle != own_collider;
+    // (angle to the left of the bow, way to turn on a hit)
+    let whiskers = [(WHISKER_ANGLE, t.right()), (0.0, t.right()), (-WHISKER_ANGLE, t.left())];
+    whiskers.iter().fold(Vec3::ZERO, |push, (angle, away)| {
+        let direction = Quat::from_rotation_y(*angle) * t.forward();
+        let ray = Ray::new(t.translation.into(), direction.into());
+        match query_pipeline.cast_ray(
+            collider_set, &ray, LOOKAHEAD, true, InteractionGroups::all(), Some(&filter)
+        ) {
+            Some((_handle, toi)) => push + *away * (1.0 - toi / LOOKAHEAD),
+            None => push
+        }
+    })
+}
+
+/// Steer back in when close to, or beyond, the edge of the arena
+fn boundary_avoidance(arena: &ArenaBounds, position: Vec3) -> Vec3 {
+    let inward = |distance_inside: f32| (1.0 - distance_inside / ARENA_MARGIN).max(0.0);
+    Vec3::new(
+        inward(position.x - arena.min_x) - inward(arena.max_x - position.x),
+        0.0,
+        inward(position.z - arena.min_z) - inward(arena.max_z - position.z)
+    )
+}
+
+pub fn enemy_movement_ai(
+    mut enemy_ships: Query<(Entity, &mut Ship, &mut Sails, &mut EnemyAi, &Transform), Without<Player>>,
+    player_ts: Query<&Transform, With<Player>>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+    wave_state: Res<WaveState>,
+    config: Res<GameConfig>
+) {
+    let collider_set = QueryPipelineColliderComponentsSet(&collider_query);
+    // the first ship launched leads the fleet, the rest take formation slots in order
+    let mut fleet: Vec<(Entity, Transform, u64)> = enemy_ships.iter()
+        .map(|(ent, _, _, ai, t)| (ent, *t, ai.launched))
+        .collect();
+    fleet.sort_by_key(|(_, _, launched)| *launched);
+    let formation = wave_state.wave.as_ref().and_then(|wave| wave.formation);
+    let positions: Vec<Vec3> = fleet.iter().map(|(_, t, _)| t.translation).collect();
+    let total: Vec3 = positions.iter().sum();
+    for (ent, mut ship, mut sails, mut ai, t) in enemy_ships.iter_mut() {
+        let to_player = nearest_player(player_ts.iter(), t.translation)
+            .map(|player_t| player_t.translation - t.translation);
+        // the middle of the rest of the fleet, or home when sailing alone
+        let rally_point =
+            if positions.len() > 1 {
+                (total - t.translation) / (positions.len() - 1) as f32
+            } else {
+                ai.home
+            };
+        let apart = positions.len() > 1 && rally_point.distance(t.translation) > REGROUP_DISTANCE;
+        ai.behaviour = ai.decide(ship.health, to_player, apart);
+        // cruise on patrol, crowd on sail to chase, run or rejoin
+        sails.state = match ai.behaviour {
+            Behaviour::Patrol | Behaviour::Broadside => SailState::Half,
+            Behaviour::Approach | Behaviour::Flee | Behaviour::Regroup => SailState::Full
+        };
+
+        let mut direction = match (ai.behaviour, to_player) {
+            (Behaviour::Approach, Some(to_player)) => to_player,
+            (Behaviour::Broadside, Some(to_player)) => {
+                // sail at right angles to the player, whichever way is nearer the
+                // current heading, and drift in or out to hold the range
+                let mut tangent = to_player.cross(Vec3::Y).normalize();
+                if tangent.dot(t.forward()) < 0.0 {
+                    tangent = -tangent;
+                }
+                let range_error = (to_player.length() - ai.personality.broadside_range) / ai.personality.broadside_range;
+                // back off when the player gets right under the bow
+                if range_error < -0.5 && to_player.dot(t.forward()) > 0.0 {
+                    sails.state = SailState::Backed;
+                }
+                tangent + to_player.normalize() * range_error
+            },
+            (Behaviour::Flee, Some(to_player)) => -to_player,
+            (Behaviour::Regroup, _) => rally_point - t.translation,
+            _ => {
+                if ai.patrol_point().distance(t.translation) < WAYPOINT_REACHED {
+                    ai.patrol_angle = (ai.patrol_angle + consts::FRAC_PI_2) % consts::TAU;
+                }
+                ai.patrol_point() - t.translation
+            }
+        };
+        if let (Some(formation), Some((flagship, flagship_t, _))) = (formation, fleet.first()) {
+            if ai.behaviour.keeps_station() && *flagship != ent {
+                let index = fleet.iter().position(|(other, _, _)| *other == ent).unwrap_or(0);
+                let slot = flagship_t.translation + flagship_t.rotation * formation.slot(index);
+                direction = flagship_t.forward() + (slot - t.translation) / FORMATION_SPACING;
+                // ease off to let the slot catch up when it has fallen astern
+                sails.state =
+                    if (slot - t.translation).dot(t.forward()) < 0.0 {
+                        SailState::Half
+                    } else {
+                        SailState::Full
+                    };
+            }
+        }
+
+        let others = positions.iter().copied().chain(player_ts.iter().map(|player_t| player_t.translation));
+        let steering = direction.normalize_or_zero() +
+            separation(t.translation, others) * SEPARATION_WEIGHT +
+            obstacle_avoidance(&query_pipeline, &collider_set, ent, t) * AVOIDANCE_WEIGHT +
+            boundary_avoidance(&config.arena.bounds, t.translation);
+        steer_along(&mut ship.steering_wheel, t, steering, HELM_RATE * INPUT_STEP as f32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_ai(broadside_range: f32) -> EnemyAi {
+        let personality = Personality { sight_range: 40.0, broadside_range, flee_health: 0.25 };
+        EnemyAi::new(1, Vec3::ZERO, 100, personality)
+    }
+
+    #[test]
+    fn decide_follows_personality_thresholds() {
+        let pirate = test_ai(10.0);
+        // a broadside range of 0, as fire ships have, means sailing straight in to ram
+        let fire_ship = test_ai(0.0);
+        let ahead = |distance: f32| Some(Vec3::new(distance, 0.0, 0.0));
+        // (ship, health, to player, apart from the fleet, behaviour picked, keeps station in formation)
+        let cases = [
+            (&pirate, 100, None, false, Behaviour::Patrol, true),
+            (&pirate, 100, None, true, Behaviour::Regroup, true),
+            (&pirate, 20, None, false, Behaviour::Regroup, true),
+            // out of sight counts as no player at all
+            (&pirate, 100, ahead(40.1), false, Behaviour::Patrol, true),
+            (&pirate, 20, ahead(40.1), false, Behaviour::Regroup, true),
+            (&pirate, 100, ahead(40.0), false, Behaviour::Approach, false),
+            // approaches until within one and a half broadside ranges
+            (&pirate, 100, ahead(15.1), false, Behaviour::Approach, false),
+            (&pirate, 100, ahead(15.0), false, Behaviour::Broadside, false),
+            (&pirate, 100, ahead(5.0), false, Behaviour::Broadside, false),
+            (&pirate, 100, ahead(5.0), true, Behaviour::Broadside, false),
+            // flees below its flee fraction of full health, but not at it
+            (&pirate, 24, ahead(20.0), false, Behaviour::Flee, false),
+            (&pirate, 24, ahead(5.0), true, Behaviour::Flee, false),
+            (&pirate, 25, ahead(20.0), false, Behaviour::Approach, false),
+            (&pirate, 25, ahead(5.0), false, Behaviour::Broadside, false),
+            // fire ships leave formation to ram whatever they sight
+            (&fire_ship, 100, ahead(30.0), false, Behaviour::Approach, false),
+            (&fire_ship, 100, ahead(0.5), false, Behaviour::Approach, false)
+        ];
+        for (ai, health, to_player, apart, expected, keeps_station) in cases {
+            let behaviour = ai.decide(health, to_player, apart);
+            assert_eq!(
+                behaviour, expected,
+                "health {}, to player {:?}, apart {}", health, to_player, apart
+            );
+            assert_eq!(behaviour.keeps_station(), keeps_station, "{:?}", behaviour);
+        }
+    }
+
+    #[test]
+    fn decide_never_flees_without_a_flee_fraction() {
+        let mut ai = test_ai(10.0);
+        ai.personality.flee_health = 0.0;
+        assert_eq!(ai.decide(1, Some(Vec3::new(20.0, 0.0, 0.0)), false), Behaviour::Approach);
+        assert_eq!(ai.decide(1, None, false), Behaviour::Patrol);
+    }
+}
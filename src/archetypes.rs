@@ -0,0 +1,76 @@
+use bevy::reflect::TypeUuid;
+use serde::Deserialize;
+
+use std::collections::HashMap;
+
+use crate::config::ConfigFile;
+
+/// Ranges that each enemy's personality is rolled from, as (min, max)
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct AiProfile {
+    pub sight_range: (f32, f32),
+    pub broadside_range: (f32, f32),
+    pub flee_health: (f32, f32)
+}
+
+/// One class of enemy ship
+#[derive(Clone, Debug, Deserialize)]
+pub struct Archetype {
+    /// Scene under `assets/`, e.g. "models/pirate/ship_dark.glb#Scene0"
+    pub model: String,
+    pub model_scale: f32,
+    /// Colour the model is painted over with, as (r, g, b), so classes sharing
+    /// a model can be told apart
+    #[serde(default)]
+    pub tint: Option<(f32, f32, f32)>,
+    /// Half extents of the hull, as (x, y, z)
+    pub collider: (f32, f32, f32),
+    /// Health in the first wave, scaled up by difficulty after that
+    pub health: i32,
+    /// Multiplier on ship thrust and torque
+    pub speed: f32,
+    /// Guns on each side; ships without any don't shoot
+    pub cannons: u32,
+    pub cannon_cooldown: f64,
+    /// Gunner skill from 0 (wild) to 1 (perfect) in the first wave, scaled up by difficulty after that
+    pub accuracy: f32,
+    /// Damage done by ramming a player, at the cost of the ship itself
+    #[serde(default)]
+    pub ram_damage: i32,
+    pub ai: AiProfile
+}
+
+/// Every enemy class by name, for waves to pick from
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "6b0c35e4-2a8d-4a4b-9a3e-5f1c7d2e8b91"]
+pub struct Archetypes {
+    pub archetypes: HashMap<String, Archetype>
+}
+
+impl ConfigFile for Archetypes {
+    const PATH: &'static str = "config/default.ships.ron";
+    const EXTENSION: &'static str = "ships.ron";
+}
+
+impl Archetypes {
+    /// Picks one of the named archetypes at random, in proportion to its
+    /// weight. Names that don't match an archetype are ignored, and there is
+    /// nothing to pick if none of the rest has a positive weight.
+    pub fn pick(&self, weights: &[(String, f32)]) -> Option<&Archetype> {
+        let known: Vec<(&Archetype, f32)> = weights.iter()
+            .filter_map(|(name, weight)| self.archetypes.get(name).map(|archetype| (archetype, weight.max(0.0))))
+            .collect();
+        let total: f32 = known.iter().map(|(_, weight)| weight).sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut roll = rand::random::<f32>() * total;
+        for (archetype, weight) in known.iter() {
+            if roll < *weight {
+                return Some(archetype);
+            }
+            roll -= weight;
+        }
+        known.last().map(|(archetype, _)| *archetype)
+    }
+}
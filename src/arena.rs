@@ -0,0 +1,171 @@
+use bevy::prelude::*;
+use bevy_prototype_debug_lines::DebugLines;
+
+use crate::{GameClock, Player, PlayerCount, Ship};
+use crate::config::GameConfig;
+use crate::damage::{DamageEvent, DamageKind};
+
+const BORDER_COLOUR: Color = Color::rgb(1.0, 0.3, 0.2);
+// Distance between the buoys marking the edge
+const BUOY_SPACING: f32 = 10.0;
+// Seconds between each toll the sea takes once the warning has run out
+const DAMAGE_INTERVAL: f64 = 1.0;
+
+/// On a ship that has left the arena
+#[derive(Component)]
+pub struct OutOfBounds {
+    /// When the ship left
+    since: f64,
+    /// When the sea last took its toll
+    last_damage: f64
+}
+
+#[derive(Component)]
+pub struct ArenaWarning;
+
+/// Marks the edge of the arena with buoys, and readies the warning shown to
+/// players who stray over it
+pub fn arena_setup(
+    mut commands: Commands,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    asset_server: Res<AssetServer>,
+    config: Res<GameConfig>
+) {
+    let bounds = config.arena.bounds;
+    let mesh = meshes.add(Mesh::from(bevy::prelude::shape::Icosphere { radius: 0.6, subdivisions: 2 }));
+    let colours = [materials.add(BORDER_COLOUR.into()), materials.add(Color::WHITE.into())];
+    let corners = [
+        Vec3::new(bounds.min_x, 0.0, bounds.min_z),
+        Vec3::new(bounds.max_x, 0.0, bounds.min_z),
+        Vec3::new(bounds.max_x, 0.0, bounds.max_z),
+        Vec3::new(bounds.min_x, 0.0, bounds.max_z)
+    ];
+    let mut buoy = 0;
+    for (i, from) in corners.iter().enumerate() {
+        let to = corners[(i + 1) % corners.len()];
+        let count = (from.distance(to) / BUOY_SPACING).ceil().max(1.0) as usize;
+        for step in 0..count {
+            commands.spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                // alternating red and white
+                material: colours[buoy % colours.len()].clone(),
+                transform: Transform::from_translation(from.lerp(to, step as f32 / count as f32)),
+                ..Default::default()
+            });
+            buoy += 1;
+        }
+    }
+
+    commands.spawn_bundle(TextBundle {
+        style: Style {
+            align_self: AlignSelf::FlexEnd,
+            position_type: PositionType::Absolute,
+            // below the HUD, which grows with the number of players
+            position: Rect {
+                bottom: Val::Px(150.0),
+                left: Val::Px(100.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: asset_server.load("fonts/Arial Unicode.ttf"),
+                font_size: 50.0,
+                color: BORDER_COLOUR,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }).insert(ArenaWarning);
+}
+
+/// Draws the edge of the arena on the water
+pub fn arena_border(
+    mut lines: ResMut<DebugLines>,
+    config: Res<GameConfig>
+) {
+    let bounds = config.arena.bounds;
+    let corners = [
+        Vec3::new(bounds.min_x, 0.5, bounds.min_z),
+        Vec3::new(bounds.max_x, 0.5, bounds.min_z),
+        Vec3::new(bounds.max_x, 0.5, bounds.max_z),
+        Vec3::new(bounds.min_x, 0.5, bounds.max_z)
+    ];
+    for (i, from) in corners.iter().enumerate() {
+        lines.line_colored(*from, corners[(i + 1) % corners.len()], 0.0, BORDER_COLOUR);
+    }
+}
+
+/// Starts the clock on ships that leave the arena, and wears them down once
+/// they have been gone longer than the warning allows
+pub fn arena_boundary(
+    mut commands: Commands,
+    mut ships: Query<(Entity, &GlobalTransform, Option<&mut OutOfBounds>), With<Ship>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    let now = clock.elapsed;
+    for (ent, gt, out_of_bounds) in ships.iter_mut() {
+        let inside = config.arena.bounds.contains(gt.translation);
+        match out_of_bounds {
+            Some(_) if inside => {
+                commands.entity(ent).remove::<OutOfBounds>();
+            },
+            None if !inside => {
+                commands.entity(ent).insert(OutOfBounds { since: now, last_damage: now });
+            },
+            Some(mut out_of_bounds) => {
+                let warned = now - out_of_bounds.since >= config.arena.warning_time;
+                if warned && now - out_of_bounds.last_damage >= DAMAGE_INTERVAL {
+                    out_of_bounds.last_damage = now;
+                    damage_events.send(DamageEvent {
+                        source: None,
+                        target: ent,
+                        amount: config.arena.damage,
+                        kind: DamageKind::LostAtSea
+                    });
+                }
+            },
+            None => ()
+        }
+    }
+}
+
+/// Counts down for each player outside the arena
+pub fn arena_warning(
+    mut text_query: Query<&mut Text, With<ArenaWarning>>,
+    players: Query<(&Player, &OutOfBounds)>,
+    player_count: Res<PlayerCount>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    if let Some(mut text) = text_query.iter_mut().next() {
+        let mut warnings: Vec<(usize, String)> = players.iter()
+            .map(|(player, out_of_bounds)| {
+                let remaining = (config.arena.warning_time - (clock.elapsed - out_of_bounds.since)).ceil();
+                let warning =
+                    if remaining > 0.0 {
+                        format!("Turn back! Lost at sea in {}", remaining)
+                    } else {
+                        "Lost at sea! Turn back!".to_string()
+                    };
+                (player.id, warning)
+            })
+            .collect();
+        warnings.sort_by_key(|(id, _)| *id);
+        text.sections[0].value = warnings.iter()
+            .map(|(id, warning)| {
+                if player_count.0 == 1 {
+                    warning.clone()
+                } else {
+                    format!("Player {}: {}", id + 1, warning)
+                }
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+    }
+}
@@ -0,0 +1,384 @@
+use bevy::prelude::*;
+use bevy_prototype_debug_lines::DebugLines;
+use bevy_rapier3d::prelude::*;
+
+use std::f32::consts;
+
+use crate::{spawn_enemy, EnemyCounter, GameClock, Player, Ship, SteeringWheel};
+use crate::ai::{steer_along, HELM_RATE};
+use crate::archetypes::Archetypes;
+use crate::config::GameConfig;
+use crate::damage::{DamageEvent, DamageKind};
+use crate::islands::ISLAND_GROUP;
+use crate::waves::WaveState;
+
+const MOTHERSHIP_RADIUS: f32 = 7.0;
+// Distance from the middle of the arena the mothership circles at
+const ORBIT_RADIUS: f32 = 15.0;
+const ALIEN_GLOW: Color = Color::rgb(0.4, 1.0, 0.3);
+
+/// Progress of the end-of-round boss fight
+#[derive(Default)]
+pub struct BossFight {
+    pub arrived: bool,
+    pub defeated: bool
+}
+
+/// The alien mothership that arrives once the final wave is cleared
+#[derive(Component)]
+pub struct Mothership {
+    max_health: i32,
+    /// Index into the configured phases
+    phase: usize,
+    last_sweep: f64,
+    last_minion: f64
+}
+
+/// A laser beam swinging round from the mothership
+#[derive(Component)]
+pub struct SweepingBeam {
+    boss: Entity,
+    started: f64,
+    /// Heading the sweep starts from, in radians around the y axis
+    from_yaw: f32,
+    /// Players already hit by this sweep, who aren't hit again
+    hit: Vec<Entity>
+}
+
+#[derive(Component)]
+pub struct BossHealthBar;
+
+#[derive(Component)]
+pub struct BossHealthFill;
+
+/// Direction along the water for a heading in radians, counterclockwise from +x
+fn yaw_direction(yaw: f32) -> Vec3 {
+    Quat::from_rotation_y(yaw) * Vec3::X
+}
+
+fn direction_yaw(direction: Vec3) -> f32 {
+    (-direction.z).atan2(direction.x)
+}
+
+pub fn boss_setup(
+    mut commands: Commands,
+    mut boss_fight: ResMut<BossFight>
+) {
+    *boss_fight = BossFight::default();
+    // health bar, hidden until the mothership arrives
+    commands.spawn_bundle(NodeBundle {
+        style: Style {
+            display: Display::None,
+            position_type: PositionType::Absolute,
+            position: Rect {
+                bottom: Val::Px(20.0),
+                left: Val::Percent(25.0),
+                ..Default::default()
+            },
+            size: Size::new(Val::Percent(50.0), Val::Px(20.0)),
+            ..Default::default()
+        },
+        color: Color::rgba(0.0, 0.0, 0.0, 0.5).into(),
+        ..Default::default()
+    })
+    .with_children(|bar| {
+        bar.spawn_bundle(NodeBundle {
+            style: Style {
+                size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                ..Default::default()
+            },
+            color: ALIEN_GLOW.into(),
+            ..Default::default()
+        })
+        .insert(BossHealthFill);
+    })
+    .insert(BossHealthBar);
+}
+
+pub fn boss_arrival(
+    mut commands: Commands,
+    mut boss_fight: ResMut<BossFight>,
+    wave_state: Res<WaveState>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    config: Res<GameConfig>,
+    clock: Res<GameClock>
+) {
+    if boss_fight.arrived || !wave_state.complete {
+        return;
+    }
+    boss_fight.arrived = true;
+    let arena = config.arena.bounds;
+    let position = Vec3::new(arena.min_x + MOTHERSHIP_RADIUS, 0.0, arena.centre().z);
+    let hull = materials.add(StandardMaterial {
+        base_color: Color::rgb(0.3, 0.3, 0.35),
+        metallic: 0.8,
+        ..Default::default()
+    });
+    let glow = materials.add(StandardMaterial {
+        base_color: ALIEN_GLOW,
+        emissive: ALIEN_GLOW,
+        ..Default::default()
+    });
+    commands.spawn_bundle(RigidBodyBundle {
+        position: position.into(),
+        forces: RigidBodyForces {
+            gravity_scale: 0.0,
+            ..Default::default()
+        }.into(),
+        damping: RigidBodyDamping { linear_damping: 3.0, angular_damping: 3.0 }.into(),
+        mass_properties: (
+            RigidBodyMassPropsFlags::TRANSLATION_LOCKED_Y |
+            RigidBodyMassPropsFlags::ROTATION_LOCKED_X |
+            RigidBodyMassPropsFlags::ROTATION_LOCKED_Z
+        ).into(),
+        ..Default::default()
+    })
+    .insert_bundle(ColliderBundle {
+        shape: ColliderShape::cylinder(2.0, MOTHERSHIP_RADIUS).into(),
+        collider_type: ColliderType::Solid.into(),
+        material: ColliderMaterial { friction: 2.0, restitution: 0.5, ..Default::default() }.into(),
+        mass_properties: ColliderMassProps::Density(1.0).into(),
+        // it flies, so islands don't get in its way
+        flags: ColliderFlags {
+            collision_groups: InteractionGroups::new(u32::MAX, !ISLAND_GROUP),
+            ..Default::default()
+        }.into(),
+        ..Default::default()
+    })
+    .insert(Transform::default())
+    .insert(RigidBodyPositionSync::Discrete)
+    .insert(RigidBodyTypeComponent::from(RigidBodyType::Dynamic))
+    .with_children(|ship| {
+        // saucer with a glowing dome
+        ship.spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(bevy::prelude::shape::Torus {
+                radius: MOTHERSHIP_RADIUS - 1.5,
+                ring_radius: 1.5,
+                ..Default::default()
+            })),
+            material: hull,
+            transform: Transform::from_xyz(0.0, 1.0, 0.0),
+            ..Default::default()
+        });
+        ship.spawn_bundle(PbrBundle {
+            mesh: meshes.add(Mesh::from(bevy::prelude::shape::Icosphere { radius: 3.0, subdivisions: 3 })),
+            material: glow,
+            transform: Transform::from_xyz(0.0, 1.5, 0.0).with_scale(Vec3::new(1.0, 0.6, 1.0)),
+            ..Default::default()
+        });
+    })
+    .insert(Ship {
+        steering_wheel: SteeringWheel::default(),
+        health: config.boss.health,
+        speed: config.boss.speed,
+        last_hit: None
+    })
+    .insert(Mothership {
+        max_health: config.boss.health,
+        phase: 0,
+        last_sweep: clock.elapsed,
+        last_minion: clock.elapsed
+    });
+}
+
+/// Moves the mothership on to later phases as it is worn down
+pub fn boss_phases(
+    mut bosses: Query<(&mut Mothership, &Ship)>,
+    mut boss_fight: ResMut<BossFight>,
+    config: Res<GameConfig>
+) {
+    for (mut boss, ship) in bosses.iter_mut() {
+        if ship.health <= 0 {
+            boss_fight.defeated = true;
+            continue;
+        }
+        let health = ship.health as f32 / boss.max_health as f32;
+        // the last phase whose threshold has been reached
+        let phase = config.boss.phases.iter()
+            .rposition(|phase| health <= phase.below_health)
+            .unwrap_or(0);
+        if phase != boss.phase {
+            boss.phase = phase;
+        }
+    }
+}
+
+pub fn boss_movement(
+    mut bosses: Query<(&mut Ship, &Transform), With<Mothership>>,
+    time: Res<Time>,
+    config: Res<GameConfig>
+) {
+    let centre = config.arena.bounds.centre();
+    for (mut ship, t) in bosses.iter_mut() {
+        // head for a point a little further round the circle
+        let yaw = direction_yaw(t.translation - centre) + 0.5;
+        let target = centre + yaw_direction(yaw) * ORBIT_RADIUS;
+        steer_along(&mut ship.steering_wheel, t, target - t.translation, HELM_RATE * time.delta_seconds());
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn boss_attacks(
+    mut commands: Commands,
+    mut bosses: Query<(Entity, &mut Mothership, &Transform)>,
+    mut players: Query<(
+        &Transform,
+        &mut RigidBodyVelocityComponent,
+        &RigidBodyMassPropsComponent
+    ), With<Player>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    mut lines: ResMut<DebugLines>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    mut enemy_counter: ResMut<EnemyCounter>,
+    archetypes: Res<Archetypes>,
+    wave_state: Res<WaveState>,
+    config: Res<GameConfig>,
+    clock: Res<GameClock>,
+    time: Res<Time>
+) {
+    let now = clock.elapsed;
+    let boss_config = &config.boss;
+    for (boss_ent, mut boss, t) in bosses.iter_mut() {
+        let phase = match boss_config.phases.get(boss.phase) {
+            Some(phase) => phase,
+            None => continue
+        };
+        let nearest = players.iter()
+            .map(|(player_t, _, _)| player_t.translation)
+            .min_by(|a, b| a.distance_squared(t.translation).total_cmp(&b.distance_squared(t.translation)));
+
+        // sweep a laser across the nearest player
+        if let Some(player_pos) = nearest {
+            if phase.laser_sweeps && now - boss.last_sweep > boss_config.sweep_interval {
+                boss.last_sweep = now;
+                let from_yaw = direction_yaw(player_pos - t.translation) - boss_config.sweep_arc / 2.0;
+                commands.spawn_bundle(PbrBundle {
+                    mesh: meshes.add(Mesh::from(bevy::prelude::shape::Capsule {
+                        radius: 0.3,
+                        rings: 1,
+                        depth: boss_config.laser_range,
+                        ..Default::default()
+                    })),
+                    material: materials.add(StandardMaterial {
+                        base_color: ALIEN_GLOW,
+                        emissive: ALIEN_GLOW,
+                        ..Default::default()
+                    }),
+                    // placed by beam_sweep before it is first drawn
+                    visibility: Visibility { is_visible: false },
+                    ..Default::default()
+                })
+                .insert(SweepingBeam { boss: boss_ent, started: now, from_yaw, hit: Vec::new() });
+                audio.play(asset_server.load("sounds/laser.ogg"));
+            }
+        }
+
+        // reel in players that stray too close
+        if phase.tractor_beam {
+            for (player_t, mut rbv, rbmp) in players.iter_mut() {
+                let to_boss = t.translation - player_t.translation;
+                if to_boss.length() < boss_config.tractor_range {
+                    let pull = to_boss.normalize() * boss_config.tractor_force * time.delta_seconds();
+                    rbv.apply_impulse(rbmp, pull.into());
+                    lines.line_colored(t.translation + Vec3::Y * 2.0, player_t.translation, 0.0, ALIEN_GLOW);
+                }
+            }
+        }
+
+        // launch minions from around the hull
+        if phase.minions && now - boss.last_minion > boss_config.minion_interval {
+            boss.last_minion = now;
+            if let Some(archetype) = archetypes.archetypes.get(&boss_config.minion) {
+                let yaw = rand::random::<f32>() * consts::TAU;
+                let position = t.translation + yaw_direction(yaw) * (MOTHERSHIP_RADIUS + 5.0);
+                spawn_enemy(
+                    &mut commands, &asset_server, &mut enemy_counter, archetype, position,
+                    Quat::from_rotation_y(yaw), wave_state.difficulty
+                );
+            } else {
+                warn!("Mothership minion archetype {} doesn't exist", boss_config.minion);
+            }
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn beam_sweep(
+    mut commands: Commands,
+    mut beams: Query<(Entity, &mut SweepingBeam, &mut Transform, &mut Visibility)>,
+    bosses: Query<&Transform, (With<Mothership>, Without<SweepingBeam>)>,
+    players: Query<Entity, With<Player>>,
+    query_pipeline: Res<QueryPipeline>,
+    collider_query: QueryPipelineColliderComponentsQuery,
+    mut damage_events: EventWriter<DamageEvent>,
+    config: Res<GameConfig>,
+    clock: Res<GameClock>
+) {
+    let boss_config = &config.boss;
+    let collider_set = QueryPipelineColliderComponentsSet(&collider_query);
+    for (beam_ent, mut beam, mut beam_t, mut visibility) in beams.iter_mut() {
+        let progress = ((clock.elapsed - beam.started) / boss_config.sweep_duration) as f32;
+        let boss_t = match bosses.get(beam.boss) {
+            Ok(boss_t) if progress < 1.0 => boss_t,
+            _ => {
+                commands.entity(beam_ent).despawn_recursive();
+                continue;
+            }
+        };
+        let direction = yaw_direction(beam.from_yaw + boss_config.sweep_arc * progress);
+        let origin = boss_t.translation + Vec3::Y * 2.0 + direction * MOTHERSHIP_RADIUS;
+        // the same shape cast as the ship's laser, along the beam
+        let shape = Ball::new(1.0);
+        let shape_pos = origin.into();
+        let shape_vel = direction.into();
+        let own_collider: ColliderHandle = beam.boss.handle();
+        let filter = |handle: ColliderHandle| handle != own_collider;
+        let hit = query_pipeline.cast_shape(
+            &collider_set, &shape_pos, &shape_vel, &shape, boss_config.laser_range,
+            InteractionGroups::all(), Some(&filter)
+        );
+        // the beam is drawn as far as whatever stops it, whether a player or the shore.
+        // Its mesh spans the full range, so it is scaled down to the reach.
+        let reach = hit.map_or(boss_config.laser_range, |(_, hit)| hit.toi);
+        *beam_t = Transform::from_translation(origin + direction * reach / 2.0)
+            .with_rotation(Quat::from_rotation_arc(Vec3::Y, direction))
+            .with_scale(Vec3::new(1.0, reach / boss_config.laser_range, 1.0));
+        visibility.is_visible = true;
+
+        if let Some((handle, _)) = hit {
+            let target = handle.entity();
+            if players.get(target).is_ok() && !beam.hit.contains(&target) {
+                beam.hit.push(target);
+                damage_events.send(DamageEvent {
+                    source: Some(beam.boss),
+                    target,
+                    amount: boss_config.laser_damage,
+                    kind: DamageKind::Laser
+                });
+            }
+        }
+    }
+}
+
+pub fn boss_health_bar(
+    mut bars: Query<&mut Style, (With<BossHealthBar>, Without<BossHealthFill>)>,
+    mut fills: Query<&mut Style, With<BossHealthFill>>,
+    bosses: Query<(&Mothership, &Ship)>
+) {
+    let boss = bosses.iter().next();
+    for mut style in bars.iter_mut() {
+        let display = if boss.is_some() { Display::Flex } else { Display::None };
+        if style.display != display {
+            style.display = display;
+        }
+    }
+    if let Some((boss, ship)) = boss {
+        let fraction = (ship.health.max(0) as f32 / boss.max_health as f32) * 100.0;
+        for mut style in fills.iter_mut() {
+            style.size.width = Val::Percent(fraction);
+        }
+    }
+}
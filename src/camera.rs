@@ -0,0 +1,217 @@
+use bevy::prelude::*;
+
+use std::f32::consts;
+
+use crate::{Player, Ship};
+use crate::config::GameConfig;
+use crate::damage::{DamageEvent, DamageKind};
+use crate::input::{Action, PlayerActions};
+use crate::scoring::MultiKill;
+
+// The camera sits this far above and to the +x side of what it looks at
+const CAMERA_DISTANCE: f32 = 60.0;
+// Extra room left around the ships being framed, as a multiple of their spread
+const FRAME_MARGIN: f32 = 1.2;
+// Manual zoom, as a multiple of the automatic zoom, and how quickly it changes per second
+const MIN_ZOOM: f32 = 0.5;
+const MAX_ZOOM: f32 = 2.0;
+const ZOOM_RATE: f32 = 1.0;
+// Shake lost per second, and how far the view jumps at full shake
+const SHAKE_DECAY: f32 = 1.5;
+const MAX_SHAKE: f32 = 1.5;
+const CANNON_HIT_SHAKE: f32 = 0.5;
+// Shake for each ship a laser shot sinks after the first
+const MULTI_KILL_SHAKE: f32 = 0.3;
+
+/// Shakes the view of a player's ship, by an amount from 0 to 1
+pub struct ScreenShake {
+    pub player: usize,
+    pub amount: f32
+}
+
+#[derive(Component)]
+pub struct CameraRig {
+    /// Player to follow, or None to keep every player in view. Bevy 0.6 has no
+    /// per-camera viewports to split the screen with, so for now one rig with
+    /// None frames every player.
+    pub player: Option<usize>,
+    /// Point on the water the camera is looking at
+    focus: Vec3,
+    /// Orthographic scale picked to frame the action, before manual zoom
+    scale: f32,
+    zoom: f32,
+    /// Shake still to play out, from 0 to 1
+    trauma: f32
+}
+
+impl CameraRig {
+    pub fn new(player: Option<usize>) -> Self {
+        CameraRig {
+            player,
+            focus: Vec3::ZERO,
+            scale: 0.0,
+            zoom: 1.0,
+            trauma: 0.0
+        }
+    }
+
+    fn follows(&self, id: usize) -> bool {
+        self.player.is_none_or(|player| player == id)
+    }
+}
+
+/// Where a camera looking at `focus` is placed
+pub fn camera_transform(focus: Vec3) -> Transform {
+    Transform::from_translation(focus + Vec3::new(CAMERA_DISTANCE, CAMERA_DISTANCE, 0.0))
+        .looking_at(focus, Vec3::Y)
+}
+
+/// Offset on screen, right and up, of an offset across the water. The camera
+/// looks down from +x at 45 degrees, so screen right is -z and screen up is
+/// -x foreshortened.
+pub fn world_to_screen(offset: Vec3) -> Vec2 {
+    Vec2::new(-offset.z, -offset.x * consts::FRAC_1_SQRT_2)
+}
+
+/// Direction along the water for an angle on screen, counterclockwise from right
+pub fn screen_to_world(angle: f32) -> Vec3 {
+    Vec3::new(-angle.sin(), 0.0, -angle.cos())
+}
+
+/// Angle on screen, counterclockwise from right, that a direction along the
+/// water points. The inverse of `screen_to_world`.
+pub fn screen_angle(direction: Vec3) -> f32 {
+    (-direction.x).atan2(-direction.z)
+}
+
+/// Point on the water under the mouse cursor, as seen by a camera
+pub fn cursor_on_water(
+    cursor: Vec2,
+    camera: &Camera,
+    camera_t: &GlobalTransform,
+    windows: &Windows
+) -> Option<Vec3> {
+    let window = windows.get(camera.window)?;
+    let ndc = cursor / Vec2::new(window.width(), window.height()) * 2.0 - Vec2::ONE;
+    let ndc_to_world = camera_t.compute_matrix() * camera.projection_matrix.inverse();
+    let near = ndc_to_world.project_point3(ndc.extend(-1.0));
+    let far = ndc_to_world.project_point3(ndc.extend(1.0));
+    let ray = far - near;
+    if ray.y.abs() < f32::EPSILON {
+        return None;
+    }
+    Some(near + ray * (-near.y / ray.y))
+}
+
+/// Starts each round looking at the players' starting line
+pub fn camera_reset(mut rigs: Query<&mut CameraRig>) {
+    for mut rig in rigs.iter_mut() {
+        rig.focus = Vec3::ZERO;
+        // snaps to the right zoom rather than easing into it
+        rig.scale = 0.0;
+        rig.trauma = 0.0;
+    }
+}
+
+/// Shakes the camera when its players fire the laser, sink several ships
+/// with one shot or are hit by cannonballs
+pub fn camera_shake(
+    mut rigs: Query<&mut CameraRig>,
+    mut shake_events: EventReader<ScreenShake>,
+    mut damage_events: EventReader<DamageEvent>,
+    mut multi_kill_events: EventReader<MultiKill>,
+    players: Query<&Player>
+) {
+    let hits = damage_events.iter()
+        .filter(|event| event.kind == DamageKind::Cannonball)
+        .filter_map(|event| players.get(event.target).ok())
+        .map(|player| (player.id, CANNON_HIT_SHAKE));
+    let multi_kills = multi_kill_events.iter()
+        .filter_map(|multi_kill| {
+            players.get(multi_kill.shooter).ok()
+                .map(|player| (player.id, MULTI_KILL_SHAKE * (multi_kill.count - 1) as f32))
+        });
+    let shakes: Vec<(usize, f32)> = shake_events.iter()
+        .map(|shake| (shake.player, shake.amount))
+        .chain(hits)
+        .chain(multi_kills)
+        .collect();
+    for mut rig in rigs.iter_mut() {
+        for (player, amount) in shakes.iter() {
+            if rig.follows(*player) {
+                rig.trauma = (rig.trauma + amount).min(1.0);
+            }
+        }
+    }
+}
+
+/// Glides after the followed players, zooming out to fit them and any
+/// enemies close to them on screen
+#[allow(clippy::type_complexity)]
+pub fn camera_follow(
+    mut rigs: Query<(&mut CameraRig, &mut Transform, &mut OrthographicProjection)>,
+    players: Query<(&Player, &Transform), Without<CameraRig>>,
+    enemies: Query<&Transform, (With<Ship>, Without<Player>, Without<CameraRig>)>,
+    player_actions: Res<PlayerActions>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    config: Res<GameConfig>
+) {
+    let aspect_ratio = windows.get_primary().map_or(1.0, |window| window.width() / window.height());
+    let delta = time.delta_seconds();
+    // eases towards a target at the same pace whatever the frame rate
+    let catch_up = 1.0 - (-config.camera.follow_rate * delta).exp();
+    for (mut rig, mut t, mut projection) in rigs.iter_mut() {
+        let followed: Vec<(usize, Vec3)> = players.iter()
+            .filter(|(player, _)| rig.follows(player.id))
+            .map(|(player, player_t)| (player.id, player_t.translation))
+            .collect();
+        for (id, _) in followed.iter() {
+            let actions = player_actions.player(*id);
+            if actions.pressed(Action::ZoomIn) {
+                rig.zoom /= 1.0 + ZOOM_RATE * delta;
+            }
+            if actions.pressed(Action::ZoomOut) {
+                rig.zoom *= 1.0 + ZOOM_RATE * delta;
+            }
+        }
+        rig.zoom = rig.zoom.clamp(MIN_ZOOM, MAX_ZOOM);
+
+        if !followed.is_empty() {
+            let centre = followed.iter().map(|(_, position)| position).sum::<Vec3>() / followed.len() as f32;
+            let nearby_enemies = enemies.iter()
+                .map(|enemy_t| enemy_t.translation)
+                .filter(|position| {
+                    followed.iter().any(|(_, player)| player.distance(*position) < config.camera.frame_range)
+                });
+            // half the height of the view needed to fit everything around the centre
+            let needed = followed.iter()
+                .map(|(_, position)| *position)
+                .chain(nearby_enemies)
+                .map(|position| {
+                    let offset = world_to_screen(position - centre).abs();
+                    (offset.x / aspect_ratio).max(offset.y)
+                })
+                .fold(0.0, f32::max) * FRAME_MARGIN;
+            let target_scale = needed.clamp(config.camera.min_scale, config.camera.max_scale);
+            rig.focus = rig.focus.lerp(centre, catch_up);
+            rig.scale =
+                if rig.scale > 0.0 {
+                    rig.scale + (target_scale - rig.scale) * catch_up
+                } else {
+                    target_scale
+                };
+        }
+
+        rig.trauma = (rig.trauma - SHAKE_DECAY * delta).max(0.0);
+        // shake grows with the square of the trauma so small knocks stay subtle
+        let elapsed = time.seconds_since_startup() as f32;
+        let jolt = Vec3::new((elapsed * 37.0).sin(), 0.0, (elapsed * 43.0 + 1.0).sin())
+            * rig.trauma * rig.trauma * MAX_SHAKE;
+        *t = camera_transform(rig.focus + jolt);
+        let scale = rig.scale.max(config.camera.min_scale) * rig.zoom;
+        if projection.scale != scale {
+            projection.scale = scale;
+        }
+    }
+}
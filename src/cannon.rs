@@ -0,0 +1,469 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use crate::{is_to_left_of_player, GameClock, Player, Ship};
+use crate::config::GameConfig;
+use crate::damage::{DamageEvent, DamageKind};
+use crate::input::{Action, PlayerActions};
+use crate::powerups::{Buffs, PowerUp};
+use crate::waves::WaveState;
+
+const CANNONBALL_GRAVITY_SCALE: f32 = 0.1;
+// Refinements of where a moving target will be when the ball arrives
+const LEAD_ITERATIONS: usize = 4;
+// How far off a shot from a completely unskilled gunner can be
+const MAX_AIM_ERROR: f32 = 0.3;
+const MAX_SPEED_ERROR: f32 = 0.2;
+// Distance between neighbouring guns along the hull
+const GUN_SPACING: f32 = 1.5;
+
+#[derive(Component)]
+pub struct Cannon {
+    pub last_fired: f64,
+    /// Seconds between broadsides in the first wave
+    pub cooldown: f64,
+    /// Guns on each side, all fired together
+    pub count: u32,
+    /// Gunner skill from 0 to 1, where 1 fires exactly on the firing solution
+    pub accuracy: f32
+}
+
+/// The player's port and starboard cannons, which reload separately
+#[derive(Component, Default)]
+pub struct Broadsides {
+    port_last_fired: f64,
+    starboard_last_fired: f64
+}
+
+#[derive(Component)]
+pub struct Cannonball {
+    /// Ship that fired the ball, credited with any damage it does
+    owner: Entity,
+    damage: i32,
+    fired_by_player: bool
+}
+
+pub fn broadside_handler(
+    mut commands: Commands,
+    player_actions: Res<PlayerActions>,
+    mut players: Query<(Entity, &Player, &mut Broadsides, &Buffs, &Transform)>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    config: Res<GameConfig>,
+    clock: Res<GameClock>
+) {
+    let now = clock.elapsed;
+    for (ent, player, mut broadsides, buffs, t) in players.iter_mut() {
+        let actions = player_actions.player(player.id);
+        let cooldown = config.broadside_cooldown * buffs.factor(PowerUp::RapidFire, now, config.powerups.reload);
+        let cannonball = || Cannonball {
+            owner: ent,
+            damage: config.cannonball_damage,
+            fired_by_player: true
+        };
+        if actions.pressed(Action::FirePort) &&
+            now - broadsides.port_last_fired > cooldown
+        {
+            let velocity = t.left() * config.cannonball_speed;
+            fire_cannon(&mut commands, muzzle(t, t.left()), velocity, cannonball(), &asset_server, &audio);
+            broadsides.port_last_fired = now;
+        }
+        if actions.pressed(Action::FireStarboard) &&
+            now - broadsides.starboard_last_fired > cooldown
+        {
+            let velocity = t.right() * config.cannonball_speed;
+            fire_cannon(&mut commands, muzzle(t, t.right()), velocity, cannonball(), &asset_server, &audio);
+            broadsides.starboard_last_fired = now;
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cannon_ai(
+    mut commands: Commands,
+    players: Query<(&Transform, &RigidBodyVelocityComponent), With<Player>>,
+    mut cannons: Query<(Entity, &mut Cannon, &Transform), Without<Player>>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    config: Res<GameConfig>,
+    rapier_config: Res<RapierConfiguration>,
+    wave_state: Res<WaveState>,
+    clock: Res<GameClock>,
+) {
+    let now = clock.elapsed;
+    let gravity = -rapier_config.gravity.y * CANNONBALL_GRAVITY_SCALE;
+    for (ent, mut cannon, t) in cannons.iter_mut() {
+        // cannon is off cooldown, which later waves reload faster
+        if now - cannon.last_fired <= cannon.cooldown / wave_state.difficulty as f64 {
+            continue;
+        }
+        let nearest = players.iter().min_by(|(a, _), (b, _)| {
+            a.translation.distance_squared(t.translation)
+                .total_cmp(&b.translation.distance_squared(t.translation))
+        });
+        let (player_t, player_rbv) = match nearest {
+            Some(player) => player,
+            None => continue
+        };
+        // player is in range
+        if player_t.translation.distance(t.translation) > config.cannon_range {
+            continue;
+        }
+        let side = if is_to_left_of_player(player_t, t) { t.left() } else { t.right() };
+        let origin = muzzle(t, side);
+        let target_velocity: Vec3 = player_rbv.linvel.into();
+        let velocity = match firing_solution(
+            origin, player_t.translation, target_velocity, config.cannonball_speed, gravity
+        ) {
+            Some(velocity) => velocity,
+            None => continue
+        };
+        // player is within the broadside arc
+        let heading = Vec3::new(velocity.x, 0.0, velocity.z);
+        if heading.angle_between(side) > config.broadside_arc {
+            continue;
+        }
+        // every gun aims on the same solution, spread along the hull
+        for gun in 0..cannon.count {
+            let cannonball = Cannonball {
+                owner: ent,
+                damage: config.cannonball_damage,
+                fired_by_player: false
+            };
+            let along_hull = (gun as f32 - (cannon.count - 1) as f32 / 2.0) * GUN_SPACING;
+            let position = origin + t.forward() * along_hull;
+            let velocity = miss(velocity, cannon.accuracy);
+            fire_cannon(&mut commands, position, velocity, cannonball, &asset_server, &audio);
+        }
+        cannon.last_fired = now;
+    }
+}
+
+/// Launch velocity at the given speed that lands a ball from `origin` on a
+/// target moving at a constant velocity, or `None` if the target is out of reach
+fn firing_solution(
+    origin: Vec3,
+    target: Vec3,
+    target_velocity: Vec3,
+    speed: f32,
+    gravity: f32
+) -> Option<Vec3> {
+    let mut aim_point = target;
+    let mut velocity = None;
+    for _ in 0..LEAD_ITERATIONS {
+        let offset = aim_point - origin;
+        let horizontal = Vec3::new(offset.x, 0.0, offset.z);
+        let distance = horizontal.length();
+        if distance <= f32::EPSILON {
+            return None;
+        }
+        let flight_time =
+            if gravity > f32::EPSILON {
+                // the flatter of the two elevations that pass through the aim point
+                let speed_sq = speed * speed;
+                let discriminant = speed_sq * speed_sq - gravity * (gravity * distance * distance + 2.0 * offset.y * speed_sq);
+                if discriminant < 0.0 {
+                    return None;
+                }
+                let elevation = ((speed_sq - discriminant.sqrt()) / (gravity * distance)).atan();
+                velocity = Some(horizontal / distance * speed * elevation.cos() + Vec3::Y * speed * elevation.sin());
+                distance / (speed * elevation.cos())
+            } else {
+                // without gravity the ball flies straight at the aim point
+                velocity = Some(offset.normalize() * speed);
+                offset.length() / speed
+            };
+        aim_point = target + target_velocity * flight_time;
+    }
+    velocity
+}
+
+/// Throws a shot off its solution by up to an amount that shrinks with accuracy
+fn miss(velocity: Vec3, accuracy: f32) -> Vec3 {
+    let error = 1.0 - accuracy.clamp(0.0, 1.0);
+    let spread = |max: f32| (rand::random::<f32>() * 2.0 - 1.0) * max * error;
+    Quat::from_rotation_y(spread(MAX_AIM_ERROR)) * velocity * (1.0 + spread(MAX_SPEED_ERROR))
+}
+
+/// Where a ball fired to one side leaves the ship, far enough out to clear the hull
+fn muzzle(ship_transform: &Transform, side: Vec3) -> Vec3 {
+    ship_transform.translation + side * 3.0 + ship_transform.up() * 2.0
+}
+
+fn fire_cannon(
+    commands: &mut Commands,
+    position: Vec3,
+    velocity: Vec3,
+    cannonball: Cannonball,
+    asset_server: &Res<AssetServer>,
+    audio: &Res<Audio>
+) {
+    let cannon = asset_server.load("sounds/cannon.ogg");
+    audio.play(cannon);
+    let cannonball_scene = asset_server.load("models/pirate/cannonball.glb#Scene0");
+    // commands.spawn_bundle(PbrBundle {
+    //     transform: t,
+    //     ..Default::default()
+    commands.spawn_bundle(RigidBodyBundle {
+        position: position.into(),
+        velocity: RigidBodyVelocity {
+            linvel: velocity.into(),
+            ..Default::default()
+        }.into(),
+        forces: RigidBodyForces {
+            gravity_scale: CANNONBALL_GRAVITY_SCALE,
+            ..Default::default()
+        }.into(),
+        ..Default::default()
+    })
+    .insert_bundle(ColliderBundle {
+        shape: ColliderShape::ball(0.5).into(),
+        collider_type: ColliderType::Solid.into(),
+        material: ColliderMaterial { friction: 0.7, restitution: 0.1, ..Default::default() }.into(),
+        mass_properties: ColliderMassProps::Density(100.0).into(),
+        flags: ActiveEvents::CONTACT_EVENTS.into(),
+        ..Default::default()
+    })
+    .with_children(|parent| {
+        parent.spawn_scene(cannonball_scene);
+    })
+    .insert(Transform::default())
+    .insert(RigidBodyPositionSync::Discrete)
+    .insert(RigidBodyTypeComponent::from(RigidBodyType::Dynamic))
+    .insert(cannonball);
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn cannonball_tracking(
+    mut commands: Commands,
+    cannonballs: Query<(Entity, &Cannonball, &Transform)>,
+    ships: Query<Option<&Player>, With<Ship>>,
+    mut contact_events: EventReader<ContactEvent>,
+    mut damage_events: EventWriter<DamageEvent>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    config: Res<GameConfig>
+) {
+    for (entity, _cannonball, t) in cannonballs.iter() {
+        // cannonball drops into the sea
+        if t.translation.y < 0.0 {
+            commands.entity(entity).despawn_recursive();
+        }
+    }
+    for contact_event in contact_events.iter() {
+        if let ContactEvent::Started(h1, h2) = contact_event {
+            for (ball, other) in [(h1, h2), (h2, h1)] {
+                let (cb_entity, cannonball, _cb_t) = match cannonballs.get(ball.entity()) {
+                    Ok(cannonball) => cannonball,
+                    Err(_) => continue
+                };
+                let explosion = asset_server.load("sounds/explosion_1.ogg");
+                audio.play(explosion);
+                commands.entity(cb_entity).despawn_recursive();
+                if let Ok(player) = ships.get(other.entity()) {
+                    let same_side = player.is_some() == cannonball.fired_by_player;
+                    let friendly_fire =
+                        if cannonball.fired_by_player {
+                            config.friendly_fire.players
+                        } else {
+                            config.friendly_fire.enemies
+                        };
+                    if !same_side || friendly_fire {
+                        damage_events.send(DamageEvent {
+                            source: Some(cannonball.owner),
+                            target: other.entity(),
+                            amount: cannonball.damage,
+                            kind: DamageKind::Cannonball
+                        });
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::{asset::FileAssetIo, ecs::event::Events, tasks::TaskPool};
+
+    use crate::SteeringWheel;
+
+    fn test_world() -> World {
+        let mut world = World::new();
+        world.insert_resource(Events::<ContactEvent>::default());
+        world.insert_resource(Events::<DamageEvent>::default());
+        world.insert_resource(AssetServer::new(FileAssetIo::new("assets"), TaskPool::new()));
+        world.insert_resource(Audio::<AudioSource>::default());
+        world.insert_resource(ron::de::from_str::<GameConfig>(include_str!("../assets/config/game.config.ron")).unwrap());
+        world
+    }
+
+    fn spawn_ship(world: &mut World, player: bool) -> Entity {
+        let mut ship = world.spawn();
+        ship.insert(Ship {
+            steering_wheel: SteeringWheel::default(),
+            health: 100,
+            speed: 1.0,
+            last_hit: None
+        })
+        .insert(Transform::default());
+        if player {
+            ship.insert(Player { id: 0 });
+        }
+        ship.id()
+    }
+
+    fn spawn_cannonball(world: &mut World, owner: Entity, fired_by_player: bool) -> Entity {
+        world.spawn()
+            .insert(Cannonball { owner, damage: 25, fired_by_player })
+            .insert(Transform::from_xyz(0.0, 2.0, 0.0))
+            .id()
+    }
+
+    /// Runs `cannonball_tracking` once over the given contacts, returning the
+    /// damage it dealt and how many sounds it played
+    fn run_contacts(world: &mut World, contacts: &[(Entity, Entity)]) -> (Vec<DamageEvent>, usize) {
+        {
+            let mut events = world.get_resource_mut::<Events<ContactEvent>>().unwrap();
+            for (a, b) in contacts {
+                events.send(ContactEvent::Started(a.handle(), b.handle()));
+            }
+        }
+        let mut stage = SystemStage::single_threaded().with_system(cannonball_tracking);
+        stage.run(world);
+        let events = world.get_resource::<Events<DamageEvent>>().unwrap();
+        let damage = events.get_reader().iter(events).copied().collect();
+        let sounds = world.get_resource::<Audio>().unwrap().queue.read().len();
+        (damage, sounds)
+    }
+
+    /// Where a ball launched with `velocity` is after `time` seconds
+    fn ball_position(origin: Vec3, velocity: Vec3, gravity: f32, time: f32) -> Vec3 {
+        origin + velocity * time - Vec3::Y * 0.5 * gravity * time * time
+    }
+
+    #[test]
+    fn firing_solution_hits_a_stationary_target() {
+        let origin = Vec3::new(0.0, 2.0, 0.0);
+        let target = Vec3::new(0.0, 0.0, 20.0);
+        let velocity = firing_solution(origin, target, Vec3::ZERO, 8.0, 0.981).unwrap();
+
+        assert!((velocity.length() - 8.0).abs() < 1e-3);
+        let horizontal_speed = Vec3::new(velocity.x, 0.0, velocity.z).length();
+        let landing = ball_position(origin, velocity, 0.981, 20.0 / horizontal_speed);
+        assert!(landing.distance(target) < 1e-2);
+    }
+
+    #[test]
+    fn firing_solution_leads_a_moving_target() {
+        let origin = Vec3::new(0.0, 2.0, 0.0);
+        let target = Vec3::new(0.0, 0.0, 15.0);
+        let target_velocity = Vec3::new(2.0, 0.0, 0.0);
+        let velocity = firing_solution(origin, target, target_velocity, 8.0, 0.981).unwrap();
+
+        // aimed ahead of where the target is now
+        assert!(velocity.x > 0.0);
+        let time = (target.z - origin.z) / velocity.z;
+        let landing = ball_position(origin, velocity, 0.981, time);
+        let target_then = target + target_velocity * time;
+        assert!(landing.distance(target_then) < 0.1);
+    }
+
+    #[test]
+    fn firing_solution_gives_up_out_of_range() {
+        // the furthest a ball at 8 units/s can reach is 8² / 0.981, about 65
+        let solution = firing_solution(Vec3::ZERO, Vec3::new(100.0, 0.0, 0.0), Vec3::ZERO, 8.0, 0.981);
+        assert!(solution.is_none());
+    }
+
+    #[test]
+    fn firing_solution_fires_flat_without_gravity() {
+        let origin = Vec3::new(0.0, 2.0, 0.0);
+        let target = Vec3::new(0.0, 0.0, 20.0);
+        let velocity = firing_solution(origin, target, Vec3::ZERO, 8.0, 0.0).unwrap();
+
+        assert!(velocity.is_finite());
+        assert!((velocity - (target - origin).normalize() * 8.0).length() < 1e-3);
+    }
+
+    #[test]
+    fn cannonball_damages_the_ship_it_hits() {
+        let mut world = test_world();
+        let player = spawn_ship(&mut world, true);
+        let enemy = spawn_ship(&mut world, false);
+        let ball = spawn_cannonball(&mut world, player, true);
+
+        let (damage, sounds) = run_contacts(&mut world, &[(ball, enemy)]);
+
+        assert_eq!(damage.len(), 1);
+        assert_eq!(damage[0].source, Some(player));
+        assert_eq!(damage[0].target, enemy);
+        assert_eq!(damage[0].amount, 25);
+        assert_eq!(damage[0].kind, DamageKind::Cannonball);
+        assert_eq!(sounds, 1);
+        assert!(world.get_entity(ball).is_none());
+    }
+
+    #[test]
+    fn contact_order_does_not_matter() {
+        let mut world = test_world();
+        let enemy = spawn_ship(&mut world, false);
+        let player = spawn_ship(&mut world, true);
+        let ball = spawn_cannonball(&mut world, enemy, false);
+
+        let (damage, _) = run_contacts(&mut world, &[(player, ball)]);
+
+        assert_eq!(damage.len(), 1);
+        assert_eq!(damage[0].source, Some(enemy));
+        assert_eq!(damage[0].target, player);
+    }
+
+    #[test]
+    fn ship_collisions_without_a_cannonball_do_nothing() {
+        let mut world = test_world();
+        let player = spawn_ship(&mut world, true);
+        let enemy = spawn_ship(&mut world, false);
+
+        let (damage, sounds) = run_contacts(&mut world, &[(player, enemy)]);
+
+        assert!(damage.is_empty());
+        assert_eq!(sounds, 0);
+    }
+
+    #[test]
+    fn friendly_fire_follows_config() {
+        let mut world = test_world();
+        let enemy = spawn_ship(&mut world, false);
+        let other_enemy = spawn_ship(&mut world, false);
+        let ball = spawn_cannonball(&mut world, enemy, false);
+
+        let (damage, sounds) = run_contacts(&mut world, &[(ball, other_enemy)]);
+
+        // the ball still bursts, it just doesn't hurt its own side
+        assert!(damage.is_empty());
+        assert_eq!(sounds, 1);
+        assert!(world.get_entity(ball).is_none());
+
+        world.get_resource_mut::<GameConfig>().unwrap().friendly_fire.enemies = true;
+        let ball = spawn_cannonball(&mut world, enemy, false);
+        let (damage, _) = run_contacts(&mut world, &[(ball, other_enemy)]);
+
+        assert_eq!(damage.len(), 1);
+        assert_eq!(damage[0].target, other_enemy);
+    }
+
+    #[test]
+    fn cannonball_hitting_scenery_does_no_damage() {
+        let mut world = test_world();
+        let player = spawn_ship(&mut world, true);
+        let ball = spawn_cannonball(&mut world, player, true);
+        let rock = world.spawn().insert(Transform::default()).id();
+
+        let (damage, sounds) = run_contacts(&mut world, &[(ball, rock)]);
+
+        assert!(damage.is_empty());
+        assert_eq!(sounds, 1);
+        assert!(world.get_entity(ball).is_none());
+    }
+}
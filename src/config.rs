@@ -0,0 +1,308 @@
+use bevy::{
+    prelude::*,
+    asset::{AssetLoader, LoadContext, LoadedAsset},
+    reflect::TypeUuid,
+    utils::BoxedFuture
+};
+use serde::{de::DeserializeOwned, Deserialize};
+
+use std::marker::PhantomData;
+
+/// A RON file under `assets/` that is deserialized into a resource of the
+/// same type, and reloaded whenever the file changes. The resource is only
+/// there once the file has loaded.
+pub trait ConfigFile: TypeUuid + DeserializeOwned + Clone + Send + Sync + 'static {
+    const PATH: &'static str;
+    /// Compound extension used to pick the loader, e.g. "config.ron"
+    const EXTENSION: &'static str;
+}
+
+pub struct ConfigPlugin<T: ConfigFile>(PhantomData<T>);
+
+impl<T: ConfigFile> Default for ConfigPlugin<T> {
+    fn default() -> Self {
+        ConfigPlugin(PhantomData)
+    }
+}
+
+impl<T: ConfigFile> Plugin for ConfigPlugin<T> {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<T>()
+            .add_asset_loader(RonLoader::<T>(PhantomData))
+            .add_startup_system(config_setup::<T>)
+            .add_system(config_update::<T>);
+    }
+}
+
+struct RonLoader<T>(PhantomData<T>);
+
+impl<T: ConfigFile> AssetLoader for RonLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), anyhow::Error>> {
+        Box::pin(async move {
+            let config = ron::de::from_bytes::<T>(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(config));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        std::slice::from_ref(&T::EXTENSION)
+    }
+}
+
+/// Must run before any config is loaded, or its file won't be watched
+pub fn watch_for_changes(asset_server: Res<AssetServer>) {
+    if let Err(err) = asset_server.watch_for_changes() {
+        warn!("Config changes won't be picked up while running: {}", err);
+    }
+}
+
+/// Keeps a config file loaded so edits to it are picked up
+struct ConfigHandle<T: ConfigFile>(Handle<T>);
+
+fn config_setup<T: ConfigFile>(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>
+) {
+    commands.insert_resource(ConfigHandle::<T>(asset_server.load(T::PATH)));
+}
+
+fn config_update<T: ConfigFile>(
+    mut commands: Commands,
+    mut events: EventReader<AssetEvent<T>>,
+    assets: Res<Assets<T>>,
+    config_handle: Res<ConfigHandle<T>>
+) {
+    for event in events.iter() {
+        match event {
+            AssetEvent::Created { handle } | AssetEvent::Modified { handle } if *handle == config_handle.0 => {
+                if let Some(loaded) = assets.get(handle) {
+                    commands.insert_resource(loaded.clone());
+                }
+            },
+            _ => ()
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ArenaBounds {
+    pub min_x: f32,
+    pub max_x: f32,
+    pub min_z: f32,
+    pub max_z: f32
+}
+
+impl ArenaBounds {
+    pub fn contains(&self, position: Vec3) -> bool {
+        position.x >= self.min_x && position.x <= self.max_x &&
+            position.z >= self.min_z && position.z <= self.max_z
+    }
+
+    pub fn centre(&self) -> Vec3 {
+        Vec3::new((self.min_x + self.max_x) / 2.0, 0.0, (self.min_z + self.max_z) / 2.0)
+    }
+}
+
+/// The play area, and what happens to ships that leave it
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ArenaConfig {
+    pub bounds: ArenaBounds,
+    /// Seconds a ship can spend outside before the sea starts to wear it down
+    pub warning_time: f64,
+    /// Damage taken every second after that
+    pub damage: i32
+}
+
+/// Whether cannonballs hurt ships on the same side as whoever fired them
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct FriendlyFire {
+    pub players: bool,
+    pub enemies: bool
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct ScoringConfig {
+    pub kill_points: u32,
+    /// Extra points for each kill after the first from a single laser shot
+    pub multi_kill_bonus: u32,
+    /// Extra points for a kill made while the shooter is below `low_health_fraction` of full health
+    pub low_health_bonus: u32,
+    pub low_health_fraction: f32,
+    /// Points for every second survived
+    pub points_per_second: u32
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct CameraConfig {
+    /// Orthographic scale range the camera zooms within to frame the action,
+    /// before any manual zoom
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// Enemies this close to a player are kept in view
+    pub frame_range: f32,
+    /// How quickly the camera catches up with the players, per second
+    pub follow_rate: f32
+}
+
+/// How many of each obstacle are scattered over the arena. Some may be left
+/// out if there is no room for them.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct IslandConfig {
+    pub islands: u32,
+    pub rocks: u32,
+    pub reefs: u32,
+    /// Open water left around where ships start and spawn
+    pub keep_clear: f32
+}
+
+/// Crates of supplies that float up during a round
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct PowerUpConfig {
+    /// Range of seconds between crates, as (min, max)
+    pub spawn_interval: (f64, f64),
+    /// Most crates afloat at once
+    pub max_afloat: usize,
+    /// Seconds a crate floats before it sinks
+    pub lifetime: f64,
+    /// Seconds each timed power-up lasts
+    pub duration: f64,
+    /// Health patched up by a repair, up to full health
+    pub repair: i32,
+    /// Multipliers on the laser cooldown, broadside reload and thrust while
+    /// each power-up is running
+    pub laser_cooldown: f64,
+    pub reload: f64,
+    pub speed: f64
+}
+
+/// How the helm answers, for players and enemies alike
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct RudderConfig {
+    /// Radians per second the wheel spins back to the middle when let go
+    pub return_rate: f32,
+    /// Fraction of full lock per second the rudder can swing
+    pub swing_rate: f32,
+    /// How turning builds up towards full lock: 1 is even, higher gives
+    /// finer control near the middle
+    pub curve: f32
+}
+
+/// The laser turret mounted on each player's ship
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct TurretConfig {
+    /// Radians per second the turret turns
+    pub traverse_rate: f32,
+    /// Bearings the turret can be aimed at as (min, max), in radians from the
+    /// bow with positive to port
+    pub arc: (f32, f32)
+}
+
+/// Wind over the arena, which varies smoothly about a prevailing direction
+/// and strength
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct WindConfig {
+    /// Radians about the vertical axis of the way the wind blows, 0 being towards +x
+    pub direction: f32,
+    /// 1 drives a ship on a beam reach at its full thrust
+    pub strength: f32,
+    /// Most the strength rises or falls in a gust
+    pub gust_strength: f32,
+    /// Seconds between gusts
+    pub gust_period: f64,
+    /// Radians the wind swings either side of its prevailing direction
+    pub veer: f32,
+    /// Seconds for the wind to swing from one side and back
+    pub veer_period: f64
+}
+
+/// Attacks the mothership uses once its health has dropped far enough
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct BossPhase {
+    /// The phase starts once health is at or below this fraction of full
+    pub below_health: f32,
+    pub laser_sweeps: bool,
+    pub tractor_beam: bool,
+    pub minions: bool
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct BossConfig {
+    pub health: i32,
+    /// Multiplier on ship thrust and torque
+    pub speed: f32,
+    /// In order of decreasing health
+    pub phases: Vec<BossPhase>,
+    pub laser_damage: i32,
+    pub laser_range: f32,
+    /// Seconds between the start of one laser sweep and the next
+    pub sweep_interval: f64,
+    /// Seconds each sweep takes to swing across its arc
+    pub sweep_duration: f64,
+    /// Radians swept, centred on the nearest player
+    pub sweep_arc: f32,
+    pub tractor_range: f32,
+    pub tractor_force: f32,
+    pub minion_interval: f64,
+    /// Archetype launched as minions
+    pub minion: String
+}
+
+/// Gameplay tuning, so balance can be changed without recompiling
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "69bb4019-148d-4fb0-bb00-99888be596f6"]
+pub struct GameConfig {
+    /// Furthest an enemy will fire at a player from
+    pub cannon_range: f32,
+    /// Radians either side of straight abeam that an enemy's guns can be trained
+    pub broadside_arc: f32,
+    pub cannonball_speed: f32,
+    /// Seconds between shots from each of the player's broadsides
+    pub broadside_cooldown: f64,
+    pub cannonball_damage: i32,
+    pub friendly_fire: FriendlyFire,
+    pub laser_cooldown: f64,
+    /// How long the laser beam stays visible
+    pub laser_timeout: f64,
+    /// Seconds to charge the laser fully with the trigger all the way down
+    pub laser_charge_time: f32,
+    /// The laser's damage, range, beam radius, recoil and heat, each as (uncharged, fully charged)
+    pub laser_damage: (i32, i32),
+    pub laser_range: (f32, f32),
+    pub laser_width: (f32, f32),
+    pub laser_recoil: (f32, f32),
+    /// Heat added by each shot, where 1 overheats the gun
+    pub laser_heat: (f32, f32),
+    /// Heat added per second while holding a charge
+    pub laser_holding_heat: f32,
+    /// Heat lost per second while not charging
+    pub laser_cooling: f32,
+    /// Fraction of the damage carried on to each further ship the beam pierces
+    pub laser_pierce_falloff: f32,
+    pub turret: TurretConfig,
+    pub player_health: i32,
+    /// Thrust of a ship with a speed of 1, see `Archetype::speed`
+    pub ship_thrust: f32,
+    /// Turning force of a ship with a speed of 1 at full lock
+    pub ship_torque: f32,
+    pub rudder: RudderConfig,
+    /// Fraction of full sail the crew can set or take in per second
+    pub sail_trim_rate: f32,
+    pub wind: WindConfig,
+    pub arena: ArenaConfig,
+    pub islands: IslandConfig,
+    pub powerups: PowerUpConfig,
+    pub camera: CameraConfig,
+    pub scoring: ScoringConfig,
+    /// The mothership that arrives after the final wave
+    pub boss: BossConfig
+}
+
+impl ConfigFile for GameConfig {
+    const PATH: &'static str = "config/game.config.ron";
+    const EXTENSION: &'static str = "config.ron";
+}
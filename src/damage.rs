@@ -0,0 +1,127 @@
+use bevy::prelude::*;
+
+use std::collections::HashMap;
+
+use bevy_rapier3d::prelude::*;
+
+use crate::{EnemyCounter, GameClock, Player, Ship};
+use crate::powerups::{Buffs, PowerUp};
+use crate::scoring::{KillEvent, MultiKill};
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum DamageKind {
+    Laser,
+    Cannonball,
+    Ramming,
+    LostAtSea
+}
+
+/// All harm to ships goes through this event, so there is one place ships die
+#[derive(Clone, Copy, Debug)]
+pub struct DamageEvent {
+    /// Ship that dealt the damage, if any
+    pub source: Option<Entity>,
+    pub target: Entity,
+    pub amount: i32,
+    pub kind: DamageKind
+}
+
+/// Sent when a player's ship goes down
+pub struct PlayerSunk {
+    /// What finished the ship off
+    pub kind: DamageKind
+}
+
+/// A ship that sacrifices itself to damage any player it rams
+#[derive(Component)]
+pub struct Rammer {
+    pub damage: i32
+}
+
+pub fn ramming_system(
+    rammers: Query<(&Rammer, &Ship)>,
+    players: Query<Entity, (With<Ship>, With<Player>)>,
+    mut contact_events: EventReader<ContactEvent>,
+    mut damage_events: EventWriter<DamageEvent>
+) {
+    for contact_event in contact_events.iter() {
+        if let ContactEvent::Started(h1, h2) = contact_event {
+            for (rammer, other) in [(h1, h2), (h2, h1)] {
+                if let (Ok((ram, ship)), Ok(player)) = (rammers.get(rammer.entity()), players.get(other.entity())) {
+                    damage_events.send(DamageEvent {
+                        source: Some(rammer.entity()),
+                        target: player,
+                        amount: ram.damage,
+                        kind: DamageKind::Ramming
+                    });
+                    damage_events.send(DamageEvent {
+                        source: None,
+                        target: rammer.entity(),
+                        amount: ship.health,
+                        kind: DamageKind::Ramming
+                    });
+                }
+            }
+        }
+    }
+}
+
+pub fn damage_system(
+    mut damage_events: EventReader<DamageEvent>,
+    mut ships: Query<(&mut Ship, Option<&Buffs>)>,
+    clock: Res<GameClock>
+) {
+    for damage in damage_events.iter() {
+        if let Ok((mut ship, buffs)) = ships.get_mut(damage.target) {
+            // a shield turns aside everything but the sea
+            let shielded = damage.kind != DamageKind::LostAtSea &&
+                buffs.is_some_and(|buffs| buffs.active(PowerUp::Shield, clock.elapsed));
+            if shielded {
+                continue;
+            }
+            ship.health -= damage.amount;
+            ship.last_hit = Some(*damage);
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn death_system(
+    mut commands: Commands,
+    ships: Query<(Entity, &Ship, Option<&Player>)>,
+    mut enemy_counter: ResMut<EnemyCounter>,
+    mut kill_events: EventWriter<KillEvent>,
+    mut multi_kill_events: EventWriter<MultiKill>,
+    mut sunk_events: EventWriter<PlayerSunk>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>
+) {
+    // kills are grouped by shooter and weapon, so several ships sunk by one shot count together
+    let mut kills: HashMap<(Option<Entity>, Option<DamageKind>), u32> = HashMap::new();
+    for (entity, ship, player) in ships.iter() {
+        if ship.health > 0 {
+            continue;
+        }
+        let explosion = asset_server.load("sounds/explosion_2.ogg");
+        audio.play(explosion);
+        commands.entity(entity).despawn_recursive();
+        let last_hit = ship.last_hit;
+        if player.is_some() {
+            sunk_events.send(PlayerSunk {
+                kind: last_hit.map_or(DamageKind::Cannonball, |hit| hit.kind)
+            });
+        } else {
+            enemy_counter.dead += 1;
+            let shot = (last_hit.and_then(|hit| hit.source), last_hit.map(|hit| hit.kind));
+            *kills.entry(shot).or_insert(0) += 1;
+        }
+    }
+    for ((shooter, kind), count) in kills {
+        kill_events.send(KillEvent { shooter, count });
+        if let (Some(shooter), Some(DamageKind::Laser)) = (shooter, kind) {
+            if count > 1 {
+                multi_kill_events.send(MultiKill { shooter, count });
+            }
+        }
+    }
+}
@@ -0,0 +1,281 @@
+use bevy::{
+    prelude::*, reflect::TypeUuid
+};
+use serde::Deserialize;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::config::ConfigFile;
+
+pub const MAX_PLAYERS: usize = 4;
+// Analog buttons (triggers) count as pressed past this point
+const BUTTON_PRESS_THRESHOLD: f32 = 0.01;
+const STICK_DEADZONE: f32 = 0.5;
+
+/// Things the player can do, independent of which key or button does them
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug, Deserialize)]
+pub enum Action {
+    SteerLeft,
+    SteerRight,
+    HoistSails,
+    ReefSails,
+    ZoomIn,
+    ZoomOut,
+    FireLaser,
+    FirePort,
+    FireStarboard,
+    Restart,
+    Pause,
+    MenuUp,
+    MenuDown,
+    MenuLeft,
+    MenuRight,
+    Confirm,
+    Back
+}
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug, Deserialize)]
+pub enum Binding {
+    Key(KeyCode),
+    Mouse(MouseButton),
+    Gamepad(GamepadButtonType)
+}
+
+impl Binding {
+    fn scheme(&self) -> ControlScheme {
+        match self {
+            Binding::Key(_) | Binding::Mouse(_) => ControlScheme::KeyboardMouse,
+            Binding::Gamepad(_) => ControlScheme::Gamepad
+        }
+    }
+
+    fn label(&self) -> String {
+        match self {
+            Binding::Key(KeyCode::Return) => "enter".to_string(),
+            Binding::Key(key) => format!("{:?}", key).to_lowercase(),
+            Binding::Mouse(button) => format!("{:?} mouse button", button).to_lowercase(),
+            Binding::Gamepad(GamepadButtonType::LeftTrigger2) => "left trigger".to_string(),
+            Binding::Gamepad(GamepadButtonType::RightTrigger2) => "right trigger".to_string(),
+            Binding::Gamepad(GamepadButtonType::LeftTrigger) => "left bumper".to_string(),
+            Binding::Gamepad(GamepadButtonType::RightTrigger) => "right bumper".to_string(),
+            Binding::Gamepad(button) => format!("{:?}", button).to_lowercase()
+        }
+    }
+}
+
+/// Where a player is pointing the laser turret
+#[derive(Clone, Copy, Debug)]
+pub enum Aim {
+    /// Angle of the aiming stick on screen
+    Stick(f32),
+    /// Position of the mouse cursor in the window
+    Cursor(Vec2)
+}
+
+/// The control scheme most recently used, so prompts can match what the player is holding
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum ControlScheme {
+    Gamepad,
+    KeyboardMouse
+}
+
+/// Which inputs trigger which actions. Loaded from the bindings file and
+/// reloaded whenever it changes; other systems may also override it at runtime.
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "d6285641-4faf-4f78-9db9-702577ebdcac"]
+pub struct InputBindings {
+    /// Stick whose rotation spins the steering wheel, as (x axis, y axis)
+    pub steering_stick: (GamepadAxisType, GamepadAxisType),
+    /// Stick that aims the laser turret, as (x axis, y axis)
+    pub aiming_stick: (GamepadAxisType, GamepadAxisType),
+    pub actions: HashMap<Action, Vec<Binding>>
+}
+
+impl InputBindings {
+    pub fn rebind(&mut self, action: Action, bindings: Vec<Binding>) {
+        self.actions.insert(action, bindings);
+    }
+
+    pub fn left_handed(&self) -> bool {
+        self.steering_stick.0 == GamepadAxisType::RightStickX
+    }
+
+    /// Swap the gamepad layout between sides: steer and aim with the other
+    /// sticks and trade the left and right triggers and bumpers
+    pub fn mirror(&mut self) {
+        std::mem::swap(&mut self.steering_stick, &mut self.aiming_stick);
+        let mirrored: Vec<(Action, Vec<Binding>)> = self.actions.iter()
+            .map(|(action, bindings)| {
+                let bindings = bindings.iter().map(|binding| match binding {
+                    Binding::Gamepad(GamepadButtonType::LeftTrigger2) => Binding::Gamepad(GamepadButtonType::RightTrigger2),
+                    Binding::Gamepad(GamepadButtonType::RightTrigger2) => Binding::Gamepad(GamepadButtonType::LeftTrigger2),
+                    Binding::Gamepad(GamepadButtonType::LeftTrigger) => Binding::Gamepad(GamepadButtonType::RightTrigger),
+                    Binding::Gamepad(GamepadButtonType::RightTrigger) => Binding::Gamepad(GamepadButtonType::LeftTrigger),
+                    other => *other
+                }).collect();
+                (*action, bindings)
+            })
+            .collect();
+        for (action, bindings) in mirrored {
+            self.rebind(action, bindings);
+        }
+    }
+
+    fn bindings(&self, action: Action) -> &[Binding] {
+        self.actions.get(&action).map_or(&[], |bindings| bindings.as_slice())
+    }
+
+    /// Name of the input to press for an action, preferring the given scheme
+    pub fn prompt(&self, action: Action, scheme: ControlScheme) -> String {
+        let bindings = self.bindings(action);
+        bindings.iter()
+            .find(|binding| binding.scheme() == scheme)
+            .or_else(|| bindings.first())
+            .map_or("nothing".to_string(), |binding| binding.label())
+    }
+}
+
+impl ConfigFile for InputBindings {
+    const PATH: &'static str = "config/default.bindings.ron";
+    const EXTENSION: &'static str = "bindings.ron";
+}
+
+/// Actions held and newly pressed this frame, resolved from the raw input
+#[derive(Default)]
+pub struct ActionState {
+    pressed: HashSet<Action>,
+    just_pressed: HashSet<Action>,
+    /// How far each held action is pressed, from 0 to 1. Only analog
+    /// buttons like triggers are ever part way.
+    values: HashMap<Action, f32>,
+    /// Angle of the steering stick, when it is pushed past the deadzone
+    pub steering_angle: Option<f32>,
+    /// Where the turret is being aimed, if anywhere
+    pub aim: Option<Aim>
+}
+
+impl ActionState {
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed.contains(&action)
+    }
+
+    pub fn just_pressed(&self, action: Action) -> bool {
+        self.just_pressed.contains(&action)
+    }
+
+    pub fn value(&self, action: Action) -> f32 {
+        self.values.get(&action).copied().unwrap_or(0.0)
+    }
+}
+
+/// Action state for each player. Player N is driven by the Nth connected
+/// gamepad, and the first player also by the keyboard and mouse.
+#[derive(Default)]
+pub struct PlayerActions {
+    players: [ActionState; MAX_PLAYERS]
+}
+
+impl PlayerActions {
+    pub fn player(&self, id: usize) -> &ActionState {
+        &self.players[id]
+    }
+
+    pub fn any_just_pressed(&self, action: Action) -> bool {
+        self.players.iter().any(|actions| actions.just_pressed(action))
+    }
+
+    /// Forget this frame's presses, so the press that changed state
+    /// isn't acted on again by whatever runs next
+    pub fn consume(&mut self) {
+        for actions in self.players.iter_mut() {
+            actions.just_pressed.clear();
+        }
+    }
+}
+
+/// Connected gamepads in a stable order, so each keeps its player slot
+pub fn connected_gamepads(gamepads: &Gamepads) -> Vec<Gamepad> {
+    let mut connected: Vec<Gamepad> = gamepads.iter().copied().collect();
+    connected.sort_by_key(|gamepad| gamepad.0);
+    connected
+}
+
+#[allow(clippy::too_many_arguments)]
+pub fn action_input_system(
+    bindings: Res<InputBindings>,
+    mut player_actions: ResMut<PlayerActions>,
+    mut scheme: ResMut<ControlScheme>,
+    keys: Res<Input<KeyCode>>,
+    mouse_buttons: Res<Input<MouseButton>>,
+    gamepads: Res<Gamepads>,
+    button_inputs: Res<Input<GamepadButton>>,
+    button_axes: Res<Axis<GamepadButton>>,
+    axes: Res<Axis<GamepadAxis>>,
+    mut cursor_moved: EventReader<CursorMoved>,
+    windows: Res<Windows>
+) {
+    let connected = connected_gamepads(&gamepads);
+    // angle of a stick, when it is pushed past the deadzone
+    let stick_angle = |gamepad: Option<Gamepad>, (stick_x, stick_y): (GamepadAxisType, GamepadAxisType)| {
+        gamepad.and_then(|gamepad| {
+            let x = axes.get(GamepadAxis(gamepad, stick_x)).unwrap_or(0.0);
+            let y = axes.get(GamepadAxis(gamepad, stick_y)).unwrap_or(0.0);
+            if x.abs() > STICK_DEADZONE || y.abs() > STICK_DEADZONE {
+                Some(y.atan2(x))
+            } else {
+                None
+            }
+        })
+    };
+    // the mouse aims for the first player while they are using it
+    let cursor = windows.get_primary()
+        .and_then(|window| window.cursor_position())
+        .filter(|_| *scheme == ControlScheme::KeyboardMouse);
+    for (id, action_state) in player_actions.players.iter_mut().enumerate() {
+        let gamepad = connected.get(id).copied();
+        let binding_value = |binding: &Binding| match binding {
+            Binding::Key(key) => if id == 0 && keys.pressed(*key) { 1.0 } else { 0.0 },
+            Binding::Mouse(button) => if id == 0 && mouse_buttons.pressed(*button) { 1.0 } else { 0.0 },
+            Binding::Gamepad(button_type) => gamepad
+                .and_then(|gamepad| button_axes.get(GamepadButton(gamepad, *button_type)))
+                .map_or(0.0, |value| value.abs().min(1.0))
+        };
+        // the hardest press of any binding counts
+        let values: HashMap<Action, f32> = bindings.actions.iter()
+            .map(|(action, action_bindings)| {
+                (*action, action_bindings.iter().map(binding_value).fold(0.0, f32::max))
+            })
+            .filter(|(_, value)| *value > BUTTON_PRESS_THRESHOLD)
+            .collect();
+        let pressed: HashSet<Action> = values.keys().copied().collect();
+        action_state.just_pressed = pressed.difference(&action_state.pressed).copied().collect();
+        action_state.pressed = pressed;
+        action_state.values = values;
+
+        action_state.steering_angle = stick_angle(gamepad, bindings.steering_stick);
+        action_state.aim = stick_angle(gamepad, bindings.aiming_stick)
+            .map(Aim::Stick)
+            .or_else(|| cursor.filter(|_| id == 0).map(Aim::Cursor));
+    }
+
+    // Switch prompts over to whichever device was touched last
+    let keyboard_used = keys.get_just_pressed().next().is_some() ||
+        mouse_buttons.get_just_pressed().next().is_some() ||
+        cursor_moved.iter().next().is_some();
+    let gamepad_used = button_inputs.get_just_pressed().next().is_some() ||
+        player_actions.players.iter().any(|actions| {
+            actions.steering_angle.is_some() || matches!(actions.aim, Some(Aim::Stick(_)))
+        });
+    let detected =
+        if keyboard_used {
+            ControlScheme::KeyboardMouse
+        } else if gamepad_used {
+            ControlScheme::Gamepad
+        } else {
+            *scheme
+        };
+    // only write when it changes so change detection stays meaningful
+    if *scheme != detected {
+        *scheme = detected;
+    }
+}
@@ -0,0 +1,152 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use crate::player_start;
+use crate::config::GameConfig;
+use crate::input::MAX_PLAYERS;
+use crate::waves::WaveList;
+
+/// Collision group of islands, rocks and reefs, which the mothership flies over
+pub const ISLAND_GROUP: u32 = 1 << 1;
+// Seeds are kept short enough to read off and type back in
+const SEED_LIMIT: u64 = 1_000_000;
+// Places tried for each obstacle before leaving it out
+const PLACEMENT_TRIES: u32 = 30;
+// Open water left between neighbouring obstacles
+const CHANNEL_WIDTH: f32 = 4.0;
+
+/// Islands, rocks and reefs, which stop laser beams
+#[derive(Component)]
+pub struct Terrain;
+
+/// Which sea each round is played on
+#[derive(Default)]
+pub struct SeaSeed {
+    /// Seed entered to replay a layout, or None for a new sea every round
+    pub fixed: Option<u64>,
+    /// Seed of the sea generated most recently
+    pub current: u64
+}
+
+impl SeaSeed {
+    /// Adds a typed digit to the end of the fixed seed
+    pub fn push_digit(&mut self, digit: u64) {
+        let seed = self.fixed.unwrap_or(0) * 10 + digit;
+        self.fixed = Some(if seed < SEED_LIMIT { seed } else { digit });
+    }
+
+    /// Takes the last digit off the fixed seed, going back to random seas once none are left
+    pub fn pop_digit(&mut self) {
+        self.fixed = self.fixed.map(|seed| seed / 10).filter(|seed| *seed > 0);
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Obstacle {
+    Island,
+    Rock,
+    /// Low coral, barely above the water
+    Reef
+}
+
+impl Obstacle {
+    /// Radius as (min, max)
+    fn radius(&self) -> (f32, f32) {
+        match self {
+            Obstacle::Island => (4.0, 7.0),
+            Obstacle::Rock => (1.0, 2.0),
+            Obstacle::Reef => (2.0, 4.0)
+        }
+    }
+
+    /// Half the height, and how far the middle sits above the water line
+    fn height(&self) -> (f32, f32) {
+        match self {
+            Obstacle::Island => (2.5, 0.0),
+            Obstacle::Rock => (2.0, 0.0),
+            Obstacle::Reef => (1.0, -1.0)
+        }
+    }
+
+    fn colour(&self) -> Color {
+        match self {
+            Obstacle::Island => Color::rgb(0.35, 0.55, 0.25),
+            Obstacle::Rock => Color::rgb(0.45, 0.45, 0.45),
+            Obstacle::Reef => Color::rgb(0.9, 0.5, 0.45)
+        }
+    }
+}
+
+/// Scatters the round's islands, rocks and reefs from the seed, leaving open
+/// water around where ships start and spawn
+pub fn islands_setup(
+    mut commands: Commands,
+    mut sea_seed: ResMut<SeaSeed>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    waves: Res<WaveList>,
+    config: Res<GameConfig>
+) {
+    let seed = sea_seed.fixed.unwrap_or_else(|| rand::random::<u64>() % SEED_LIMIT);
+    sea_seed.current = seed;
+    let mut rng = StdRng::seed_from_u64(seed);
+
+    let keep_clear: Vec<Vec3> = (1..=MAX_PLAYERS)
+        .flat_map(|count| (0..count).map(move |id| player_start(id, count)))
+        .chain(waves.waves.iter().flat_map(|wave| wave.spawners.iter().map(|(x, z)| Vec3::new(*x, 0.0, *z))))
+        .collect();
+    let arena = config.arena.bounds;
+    if arena.min_x >= arena.max_x || arena.min_z >= arena.max_z {
+        warn!("Leaving the sea empty, as the arena bounds in the config enclose no water");
+        return;
+    }
+    let islands = &config.islands;
+    let mesh = meshes.add(Mesh::from(bevy::prelude::shape::Icosphere { radius: 1.0, subdivisions: 2 }));
+    // (position, radius) of everything placed so far
+    let mut placed: Vec<(Vec3, f32)> = Vec::new();
+    for (obstacle, count) in [
+        (Obstacle::Island, islands.islands),
+        (Obstacle::Rock, islands.rocks),
+        (Obstacle::Reef, islands.reefs)
+    ] {
+        let material = materials.add(obstacle.colour().into());
+        let (min_radius, max_radius) = obstacle.radius();
+        let (half_height, elevation) = obstacle.height();
+        for _ in 0..count {
+            let radius = rng.gen_range(min_radius..=max_radius);
+            let spot = (0..PLACEMENT_TRIES)
+                .map(|_| Vec3::new(rng.gen_range(arena.min_x..arena.max_x), 0.0, rng.gen_range(arena.min_z..arena.max_z)))
+                .find(|spot| {
+                    keep_clear.iter().all(|clear| clear.distance(*spot) > radius + islands.keep_clear) &&
+                        placed.iter().all(|(other, other_radius)| {
+                            other.distance(*spot) > radius + other_radius + CHANNEL_WIDTH
+                        })
+                });
+            let spot = match spot {
+                Some(spot) => spot,
+                None => continue
+            };
+            placed.push((spot, radius));
+            let position = spot + Vec3::Y * elevation;
+            commands.spawn_bundle(PbrBundle {
+                mesh: mesh.clone(),
+                material: material.clone(),
+                transform: Transform::from_translation(position).with_scale(Vec3::new(radius, half_height, radius)),
+                ..Default::default()
+            })
+            .insert_bundle(ColliderBundle {
+                shape: ColliderShape::cylinder(half_height, radius).into(),
+                position: position.into(),
+                collider_type: ColliderType::Solid.into(),
+                material: ColliderMaterial { friction: 1.0, restitution: 0.2, ..Default::default() }.into(),
+                flags: ColliderFlags {
+                    collision_groups: InteractionGroups::new(ISLAND_GROUP, u32::MAX),
+                    ..Default::default()
+                }.into(),
+                ..Default::default()
+            })
+            .insert(Terrain);
+        }
+    }
+}
@@ -1,19 +1,55 @@
 use bevy::{
-    prelude::*, core::FixedTimestep
+    prelude::*, core::FixedTimestep, ecs::schedule::ShouldRun, input::InputSystem
 };
 use bevy_prototype_debug_lines::*;
 use bevy_rapier3d::prelude::*;
+use rand::distributions::{Distribution, Standard};
 
-use std::f32::consts;
+use std::{f32::consts, ops::{Add, Mul, Sub}};
 
-const CANNON_COOLDOWN: f64 = 5.0;
-const LASER_COOLDOWN: f64 = 1.0;
-const LASER_TIMEOUT: f64 = 0.3;
-const ENEMY_COUNT: i32 = 10;
+mod ai;
+mod archetypes;
+mod arena;
+mod boss;
+mod camera;
+mod cannon;
+mod config;
+mod damage;
+mod input;
+mod islands;
+mod menu;
+mod powerups;
+mod sailing;
+mod scoring;
+mod waves;
+
+use ai::{EnemyAi, Personality};
+use archetypes::{Archetype, Archetypes};
+use boss::BossFight;
+use camera::{CameraRig, ScreenShake};
+use cannon::{Broadsides, Cannon};
+use config::{ConfigPlugin, GameConfig};
+use damage::{DamageEvent, DamageKind, PlayerSunk, Rammer};
+use input::{Action, Aim, ControlScheme, InputBindings, PlayerActions, MAX_PLAYERS};
+use islands::{SeaSeed, Terrain};
+use menu::MenuPlugin;
+use powerups::{Buffs, PickupSpawner, PowerUp};
+use sailing::{SailState, Sails, Wind};
+use scoring::{HighScores, KillEvent, MultiKill, NameEntry, Score};
+use waves::{WaveList, WaveState};
+
+// Seconds between runs of the fixed step input and AI systems
+const INPUT_STEP: f64 = 0.05;
+// How far the steering wheel turns per input step while a steering button is held
+const DIGITAL_STEER_RATE: f32 = consts::FRAC_PI_4;
+// Furthest the wheel turns either way, which puts the rudder at full lock
+const WHEEL_LIMIT: f32 = consts::TAU * 3.0;
+const HULL_DENSITY: f32 = 4.0;
+const LASER_SHAKE: f32 = 0.3;
 
 fn main() {
     App::new()
-        .add_state(GameState::Running)
+        .add_state(GameState::Loading)
         .insert_resource(Msaa { samples: 4 })
         .insert_resource(WindowDescriptor {
             title: "Yo ho ho and an extra-terrestrial gun!".to_string(),
@@ -26,55 +62,137 @@ fn main() {
             brightness: 1.0 / 5.0f32,
         })
         .insert_resource(ClearColor(Color::rgb(0.0, 0.4, 0.6)))
-        .insert_resource(PreviousInput::default())
-        .insert_resource(EnemyCounter {to_spawn: ENEMY_COUNT, dead: 0})
+        .insert_resource(ControlScheme::KeyboardMouse)
+        .insert_resource(PlayerActions::default())
+        .insert_resource(PlayerCount(1))
+        .insert_resource(GameClock::default())
+        .insert_resource(EnemyCounter {total: 0, to_spawn: 0, dead: 0, launched: 0})
+        .insert_resource(WaveState::default())
+        .insert_resource(Score::default())
+        .insert_resource(HighScores::load())
+        .insert_resource(NameEntry::default())
+        .insert_resource(BossFight::default())
+        .insert_resource(Wind::default())
+        .insert_resource(SeaSeed::default())
+        .insert_resource(PickupSpawner::default())
+        .add_event::<KillEvent>()
+        .add_event::<MultiKill>()
+        .add_event::<DamageEvent>()
+        .add_event::<PlayerSunk>()
+        .add_event::<ScreenShake>()
         .add_plugins(DefaultPlugins)
         .add_plugin(DebugLinesPlugin::default())
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
         .add_plugin(RapierRenderPlugin)
+        .add_startup_system_to_stage(StartupStage::PreStartup, config::watch_for_changes)
+        .add_plugin(ConfigPlugin::<InputBindings>::default())
+        .add_plugin(ConfigPlugin::<GameConfig>::default())
+        .add_plugin(ConfigPlugin::<WaveList>::default())
+        .add_plugin(ConfigPlugin::<Archetypes>::default())
+        .add_plugin(MenuPlugin)
         .add_startup_system(camera_setup)
+        .add_startup_system_to_stage(StartupStage::PostStartup, mark_persistent)
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            input::action_input_system
+                .with_run_criteria(configs_loaded)
+                .after(InputSystem)
+        )
+        .add_system_to_stage(
+            CoreStage::PreUpdate,
+            game_clock.with_run_criteria(gameplay_active)
+        )
+        // Gameplay gets its own stage so pausing can stop it as a whole,
+        // fixed timesteps included
+        .add_stage_before(
+            CoreStage::Update,
+            GameplayStage,
+            SystemStage::parallel().with_run_criteria(gameplay_active)
+        )
+        .add_system_set(SystemSet::on_update(GameState::Loading).with_system(config_loading))
         .add_system_set(
             SystemSet::on_enter(GameState::Running)
                 .with_system(player_setup)
                 .with_system(lighting_setup)
                 .with_system(hud_setup)
-                .with_system(spawner_setup)
+                .with_system(waves::wave_setup)
+                .with_system(scoring::score_setup)
+                .with_system(boss::boss_setup)
+                .with_system(islands::islands_setup)
+                .with_system(camera::camera_reset)
+                .with_system(arena::arena_setup)
+                .with_system(powerups::pickup_setup)
         )
-        .add_system_set(
+        .add_system_set_to_stage(
+            GameplayStage,
             SystemSet::new()
                 .with_system(
                     player_input_handler
-                        .with_run_criteria(FixedTimestep::step(0.05))
+                        .with_run_criteria(FixedTimestep::step(INPUT_STEP))
                         .label(Pipeline::Input)
                         .before(Pipeline::ShipMovement)
                 )
+                .with_system(
+                    player_sail_handler
+                        .label(Pipeline::Input)
+                )
+                .with_system(
+                    turret_aiming
+                        .label(Pipeline::Input)
+                )
                 .with_system(
                     laser_gun_handler
                         .label(Pipeline::Input)
                 )
+                .with_system(
+                    cannon::broadside_handler
+                        .label(Pipeline::Input)
+                        .before(Pipeline::CannonballMovement)
+                )
                 .with_system(
                     enemy_spawner
                         .label(Pipeline::Spawner)
                 )
+                .with_system(
+                    powerups::pickup_spawner
+                        .label(Pipeline::Spawner)
+                )
+                .with_system(powerups::pickup_bobbing)
+                .with_system(model_tinting)
+                .with_system(
+                    powerups::pickup_collection
+                        .after(Pipeline::Input)
+                        .before(Pipeline::Damage)
+                )
                 // Enemy AI system
                 .with_system(
-                    enemy_movement_ai
-                        .with_run_criteria(FixedTimestep::step(0.05))
+                    ai::enemy_movement_ai
+                        .with_run_criteria(FixedTimestep::step(INPUT_STEP))
                         .label(Pipeline::AI)
                         .before(Pipeline::ShipMovement)
                 )
                 .with_system(
-                    cannon_ai
-                        .with_run_criteria(FixedTimestep::step(0.05))
+                    cannon::cannon_ai
+                        .with_run_criteria(FixedTimestep::step(INPUT_STEP))
                         .label(Pipeline::AI)
                         .before(Pipeline::CannonballMovement)
                 )
+                .with_system(
+                    sailing::wind_system
+                        .before(Pipeline::ShipMovement)
+                )
+                .with_system(
+                    sailing::sail_handling
+                        .after(Pipeline::Input)
+                        .after(Pipeline::AI)
+                        .before(Pipeline::ShipMovement)
+                )
                 .with_system(
                     ship_movement
                         .label(Pipeline::ShipMovement)
                 )
                 .with_system(
-                    cannonball_tracking
+                    cannon::cannonball_tracking
                         .label(Pipeline::CannonballMovement)
                 )
                 .with_system(
@@ -82,43 +200,175 @@ fn main() {
                         .label(Pipeline::LaserCleanup)
                         .after(Pipeline::Input)
                 )
+                .with_system(
+                    arena::arena_boundary
+                        .before(Pipeline::Damage)
+                )
+                .with_system(arena::arena_border)
+                .with_system(
+                    arena::arena_warning
+                        .label(Pipeline::Hud)
+                        .after(Pipeline::ShipMovement)
+                )
+                .with_system(
+                    boss::boss_movement
+                        .label(Pipeline::AI)
+                        .before(Pipeline::ShipMovement)
+                )
+                .with_system(
+                    boss::boss_attacks
+                        .label(Pipeline::AI)
+                )
+                .with_system(
+                    boss::beam_sweep
+                        .label(Pipeline::AI)
+                )
+                .with_system(
+                    boss::boss_phases
+                        .after(Pipeline::Damage)
+                        .before(Pipeline::Death)
+                )
+                .with_system(
+                    boss::boss_health_bar
+                        .label(Pipeline::Hud)
+                        .after(Pipeline::Death)
+                )
+                .with_system(
+                    damage::ramming_system
+                        .before(Pipeline::Damage)
+                )
+                .with_system(
+                    damage::damage_system
+                        .label(Pipeline::Damage)
+                        .after(Pipeline::Input)
+                        .after(Pipeline::AI)
+                        .after(Pipeline::ShipMovement)
+                        .after(Pipeline::CannonballMovement)
+                )
+                .with_system(
+                    damage::death_system
+                        .label(Pipeline::Death)
+                        .after(Pipeline::Damage)
+                )
+                .with_system(
+                    camera::camera_shake
+                        .label(Pipeline::Camera)
+                        .after(Pipeline::Input)
+                        .after(Pipeline::Damage)
+                        .after(Pipeline::Death)
+                )
+                .with_system(
+                    camera::camera_follow
+                        .after(Pipeline::Camera)
+                        .after(Pipeline::ShipMovement)
+                )
                 .with_system(
                     hud_handler
-                        .label(Pipeline::HUD)
+                        .label(Pipeline::Hud)
                         .after(Pipeline::ShipMovement)
                         .after(Pipeline::Input)
                         .after(Pipeline::CannonballMovement)
                         .after(Pipeline::AI)
+                        .after(Pipeline::Death)
                 )
         )
-        .add_system_set(SystemSet::on_update(GameState::Running).with_system(game_over_checker.after(Pipeline::HUD)))
-        .add_system_set(SystemSet::on_update(GameState::GameOver).with_system(game_over))
+        .add_system_set(
+            SystemSet::on_update(GameState::Running)
+                .with_system(waves::wave_progression)
+                .with_system(boss::boss_arrival)
+                .with_system(scoring::score_system.label(Pipeline::Scoring))
+                .with_system(game_over_checker)
+        )
+        .add_system_set(SystemSet::on_enter(GameState::GameOver).with_system(scoring::high_score_setup))
+        .add_system_set(
+            SystemSet::on_update(GameState::GameOver)
+                // restart runs first, so the press that confirms a name doesn't also restart
+                .with_system(game_over.before(Pipeline::Scoring))
+                .with_system(scoring::name_entry_handler.label(Pipeline::Scoring))
+                .with_system(scoring::high_score_display.after(Pipeline::Scoring))
+        )
         .add_system_set(SystemSet::on_exit(GameState::GameOver).with_system(teardown))
-        .add_system(bevy::input::system::exit_on_esc_system)
+        // quitting to the menu mid-round leaves the round behind
+        .add_system_set(SystemSet::on_enter(GameState::MainMenu).with_system(teardown))
         .run();
 }
 
 #[derive(Clone, Eq, PartialEq, Debug, Hash)]
 enum GameState {
+    /// Waiting on the config files, before the first menu
+    Loading,
+    MainMenu,
     Running,
-    GameOver
+    Paused,
+    GameOver,
+    Settings,
+    HighScores
+}
+
+#[derive(StageLabel, Debug, Hash, PartialEq, Eq, Clone)]
+struct GameplayStage;
+
+/// Whether every config file has loaded, for systems that run whatever the state
+fn configs_loaded(
+    bindings: Option<Res<InputBindings>>,
+    config: Option<Res<GameConfig>>,
+    waves: Option<Res<WaveList>>,
+    archetypes: Option<Res<Archetypes>>
+) -> ShouldRun {
+    if bindings.is_some() && config.is_some() && waves.is_some() && archetypes.is_some() {
+        ShouldRun::Yes
+    } else {
+        ShouldRun::No
+    }
+}
+
+/// Opens the main menu once the config files have loaded
+fn config_loading(
+    bindings: Option<Res<InputBindings>>,
+    config: Option<Res<GameConfig>>,
+    waves: Option<Res<WaveList>>,
+    archetypes: Option<Res<Archetypes>>,
+    mut state: ResMut<State<GameState>>
+) {
+    if configs_loaded(bindings, config, waves, archetypes) == ShouldRun::Yes {
+        let _ = state.set(GameState::MainMenu);
+    }
+}
+
+fn gameplay_active(state: Res<State<GameState>>) -> ShouldRun {
+    match state.current() {
+        GameState::Running | GameState::GameOver => ShouldRun::Yes,
+        _ => ShouldRun::No
+    }
+}
+
+/// Seconds of play, which unlike `Time` stands still while the game is paused
+#[derive(Default)]
+struct GameClock {
+    elapsed: f64
+}
+
+fn game_clock(
+    mut clock: ResMut<GameClock>,
+    time: Res<Time>
+) {
+    clock.elapsed += time.delta_seconds_f64();
 }
 
 #[derive(Component)]
-struct HUD;
+struct Hud;
 
 #[derive(Component)]
 struct GameOverText;
 
+#[derive(Component)]
+struct HighScoreText;
+
 fn hud_setup(
     mut commands: Commands,
-    asset_server: Res<AssetServer>,
-    mut enemy_counter: ResMut<EnemyCounter>
+    asset_server: Res<AssetServer>
 ) {
-    enemy_counter.to_spawn = ENEMY_COUNT;
-    enemy_counter.dead = 0;
     let font = asset_server.load("fonts/Arial Unicode.ttf");
-    commands.spawn_bundle(UiCameraBundle::default());
     commands.spawn_bundle(TextBundle {
         style: Style {
             align_self: AlignSelf::FlexEnd,
@@ -141,7 +391,7 @@ fn hud_setup(
         ),
         ..Default::default()
     })
-    .insert(HUD);
+    .insert(Hud);
     commands.spawn_bundle(TextBundle {
         style: Style {
             align_self: AlignSelf::FlexEnd,
@@ -164,6 +414,28 @@ fn hud_setup(
         ),
         ..Default::default()
     }).insert(GameOverText);
+    commands.spawn_bundle(TextBundle {
+        style: Style {
+            align_self: AlignSelf::FlexEnd,
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(80.0),
+                right: Val::Px(15.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font: font.clone(),
+                font_size: 30.0,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    }).insert(HighScoreText);
 }
 
 #[derive(SystemLabel, Debug, Hash, PartialEq, Eq, Clone)]
@@ -174,42 +446,75 @@ enum Pipeline {
     ShipMovement,
     CannonballMovement,
     LaserCleanup,
-    HUD
+    Damage,
+    Death,
+    Scoring,
+    Camera,
+    Hud
 }
 
 #[derive(Component)]
-struct Player;
+struct Player {
+    id: usize
+}
+
+/// How many players started the current round
+struct PlayerCount(usize);
 
 #[derive(Component)]
 struct Ship {
     steering_wheel: SteeringWheel,
-    health: i32
-}
-#[derive(Component)]
-struct Cannon {
-    last_fired: f64
+    health: i32,
+    /// Multiplier on thrust and torque
+    speed: f32,
+    /// Most recent damage taken, to credit whoever sinks the ship
+    last_hit: Option<DamageEvent>
 }
 
+#[derive(Default)]
 struct SteeringWheel {
-    angle: f32
+    /// Radians the wheel is turned, positive to port
+    angle: f32,
+    /// Fraction of full lock the rudder is over, positive to port. Follows
+    /// the wheel as fast as the rudder can swing.
+    rudder: f32
 }
 
 impl SteeringWheel {
     fn turn(&mut self, delta_angle: f32) {
         self.angle += delta_angle;
-        self.angle = self.angle.clamp(
-            -consts::TAU * 3.0, 
-            consts::TAU * 3.0
-        );
+        self.angle = self.angle.clamp(-WHEEL_LIMIT, WHEEL_LIMIT);
     }
-}
 
-#[derive(Component)]
-struct Cannonball;
+    /// Turns the wheel towards `angle`, by at most `max_delta`
+    fn turn_towards(&mut self, angle: f32, max_delta: f32) {
+        self.turn((angle - self.angle).clamp(-max_delta, max_delta));
+    }
 
-#[derive(Component)]
+    /// Swings the rudder towards where the wheel is pointing, by at most `max_delta`
+    fn swing_rudder(&mut self, max_delta: f32) {
+        let target = self.angle / WHEEL_LIMIT;
+        self.rudder += (target - self.rudder).clamp(-max_delta, max_delta);
+    }
+
+    /// Fraction of the full turning force, curved so small movements of the
+    /// rudder around the middle make for gentle turns
+    fn turning(&self, curve: f32) -> f32 {
+        self.rudder.signum() * self.rudder.abs().powf(curve)
+    }
+}
+
+#[derive(Component, Default)]
 struct LaserGun {
-    last_fired: f64
+    /// Bearing the turret points at, in radians from the bow with positive to port
+    bearing: f32,
+    last_fired: f64,
+    /// Charge built up while the trigger is held, from 0 to 1
+    charge: f32,
+    /// Overheats the gun at 1
+    heat: f32,
+    /// Locked until it has cooled right down
+    overheated: bool
 }
 
 #[derive(Component)]
@@ -224,17 +529,43 @@ struct Spawner {
 }
 
 struct EnemyCounter {
+    total: i32,
     to_spawn: i32,
-    dead: i32
+    dead: i32,
+    /// Ships launched so far, which numbers each one in spawn order
+    launched: u64
 }
 
 fn player_setup(
     mut commands: Commands,
-    asset_server: Res<AssetServer>
+    asset_server: Res<AssetServer>,
+    gamepads: Res<Gamepads>,
+    mut player_count: ResMut<PlayerCount>,
+    config: Res<GameConfig>
+) {
+    // One ship per connected gamepad, or a single keyboard player
+    let count = input::connected_gamepads(&gamepads).len().clamp(1, MAX_PLAYERS);
+    player_count.0 = count;
+    for id in 0..count {
+        spawn_player_ship(&mut commands, &asset_server, &config, id, count);
+    }
+}
+
+/// Where each player's ship starts out, side by side and centred on the origin
+fn player_start(id: usize, count: usize) -> Vec3 {
+    let offset = (id as f32 - (count - 1) as f32 / 2.0) * 10.0;
+    Vec3::new(0.0, 0.0, offset)
+}
+
+fn spawn_player_ship(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    config: &GameConfig,
+    id: usize,
+    count: usize
 ) {
-    // Create the player ship
     commands.spawn_bundle(RigidBodyBundle {
-        position: Vec3::new(0.0, 0.0, 0.0).into(),
+        position: player_start(id, count).into(),
         forces: RigidBodyForces {
             gravity_scale: 0.0,
             ..Default::default()
@@ -251,7 +582,7 @@ fn player_setup(
         shape: ColliderShape::cuboid(1.8, 2.0, 4.0).into(),
         collider_type: ColliderType::Solid.into(),
         material: ColliderMaterial { friction: 2.0, restitution: 0.1, ..Default::default() }.into(),
-        mass_properties: ColliderMassProps::Density(4.0).into(),
+        mass_properties: hull_mass(1.8, 2.0, 4.0).into(),
         flags: ActiveEvents::CONTACT_EVENTS.into(),
         ..Default::default()
     })
@@ -267,7 +598,7 @@ fn player_setup(
         // Add laser gun
         let laser_t =
             Transform::from_translation(Vec3::new(1.5, 1.2, 0.0))
-                .with_rotation(Quat::from_rotation_y(consts::FRAC_PI_2))
+                .with_rotation(turret_rotation(-consts::FRAC_PI_2))
                 .with_scale(Vec3::splat(6.0));
         ship.spawn_bundle(PbrBundle {
             transform: laser_t,
@@ -278,103 +609,194 @@ fn player_setup(
                 asset_server.load("models/blasterG.glb#Scene0")
             );
         })
-        .insert(LaserGun { last_fired: 0.0 });
+        // starts out trained to starboard
+        .insert(LaserGun { bearing: -consts::FRAC_PI_2, ..Default::default() });
     })
     .insert(Ship {
-        steering_wheel: SteeringWheel {
-            angle: 0.0,
-        },
-        health: 200
+        steering_wheel: SteeringWheel::default(),
+        health: config.player_health,
+        speed: 1.0,
+        last_hit: None
     })
-    .insert(Player);
-}
-
-fn spawner_setup(
-    mut commands: Commands
-) {
-    for x in [-40.0, 30.0] {
-        for z in [-30.0, 30.0] {
-            commands.spawn_bundle(PbrBundle {
-                transform: Transform::from_translation(Vec3::new(x, 0.0, z)),
-                ..Default::default()
-            }).insert(Spawner { last_spawned: 0.0, until_next: 0.0} );
-        }
-    }
+    .insert(Sails::new(SailState::Full))
+    .insert(PreviousInput::default())
+    .insert(Broadsides::default())
+    .insert(Buffs::default())
+    .insert(Player { id });
 }
 
+#[allow(clippy::too_many_arguments)]
 fn enemy_spawner(
     mut commands: Commands,
     mut spawners: Query<(&mut Spawner, &Transform)>,
     enemies: Query<&Ship, Without<Player>>,
     asset_server: Res<AssetServer>,
     mut enemy_counter: ResMut<EnemyCounter>,
-    time: Res<Time>
+    wave_state: Res<WaveState>,
+    archetypes: Res<Archetypes>,
+    clock: Res<GameClock>
 ) {
-    if enemies.iter().count() >= 6 || enemy_counter.to_spawn <= 0 {
+    let wave = match &wave_state.wave {
+        Some(wave) => wave,
+        None => return
+    };
+    if enemies.iter().count() >= wave.max_enemies || enemy_counter.to_spawn <= 0 {
         return;
     }
     for (mut spawner, spawner_t) in spawners.iter_mut() {
-        let now = time.seconds_since_startup();
+        let now = clock.elapsed;
         let since_last_spawn = now - spawner.last_spawned;
         if since_last_spawn > spawner.until_next {
             spawner.last_spawned = now;
-            spawner.until_next = rand::random::<f64>() * 20.0 + 20.0;
+            spawner.until_next = random_in(wave.spawn_interval);
+            let archetype = match archetypes.pick(&wave.ships) {
+                Some(archetype) => archetype,
+                None => {
+                    warn!("Wave {} has no known ship archetypes with a positive weight to spawn", wave_state.number + 1);
+                    return;
+                }
+            };
             enemy_counter.to_spawn -= 1;
-            // Create enemy entity
-            commands.spawn_bundle(RigidBodyBundle {
-                position: (
-                    spawner_t.translation.clone(),
-                    Quat::from_rotation_y(rand::random::<f32>() * consts::TAU)
-                ).into(),
-                forces: RigidBodyForces {
-                    gravity_scale: 0.0,
-                    // torque: Vec3::new(140.0, 80.0, 20.0).into(),
-                    ..Default::default()
-                }.into(),
-                damping: RigidBodyDamping { linear_damping: 3.0, angular_damping: 3.0 }.into(),
-                mass_properties: (
-                    RigidBodyMassPropsFlags::TRANSLATION_LOCKED_Y |
-                    RigidBodyMassPropsFlags::ROTATION_LOCKED_X |
-                    RigidBodyMassPropsFlags::ROTATION_LOCKED_Z
-                ).into(),
-                ..Default::default()
-            })
-            .insert_bundle(ColliderBundle {
-                shape: ColliderShape::cuboid(1.8, 2.0, 4.0).into(),
-                collider_type: ColliderType::Solid.into(),
-                material: ColliderMaterial { friction: 2.0, restitution: 0.9, ..Default::default() }.into(),
-                mass_properties: ColliderMassProps::Density(4.0).into(),
-                ..Default::default()
-            })
-            .insert(Transform::default())
-            .insert(RigidBodyPositionSync::Discrete)
-            .insert(RigidBodyTypeComponent::from(RigidBodyType::Dynamic))
-            // .insert(ColliderDebugRender::with_id(1))
-            .with_children(|ship| {
-                // Add ship model
-                ship.spawn_scene(
-                    asset_server.load("models/pirate/ship_dark.glb#Scene0")
-                );
-            })
-            .insert(Ship {
-                steering_wheel: SteeringWheel {
-                    angle: 0.0
-                },
-                health: 40
-            }).insert(Cannon {
-                last_fired: 0.0
-            });
+            let rotation = Quat::from_rotation_y(rand::random::<f32>() * consts::TAU);
+            spawn_enemy(
+                &mut commands, &asset_server, &mut enemy_counter, archetype,
+                spawner_t.translation, rotation, wave_state.difficulty
+            );
         }
     }
 }
 
+fn spawn_enemy(
+    commands: &mut Commands,
+    asset_server: &Res<AssetServer>,
+    enemy_counter: &mut EnemyCounter,
+    archetype: &Archetype,
+    position: Vec3,
+    rotation: Quat,
+    difficulty: f32
+) {
+    let health = (archetype.health as f32 * difficulty).round() as i32;
+    let (half_x, half_y, half_z) = archetype.collider;
+    enemy_counter.launched += 1;
+    // Create enemy entity
+    let mut enemy = commands.spawn();
+    enemy.insert_bundle(RigidBodyBundle {
+        position: (position, rotation).into(),
+        forces: RigidBodyForces {
+            gravity_scale: 0.0,
+            // torque: Vec3::new(140.0, 80.0, 20.0).into(),
+            ..Default::default()
+        }.into(),
+        damping: RigidBodyDamping { linear_damping: 3.0, angular_damping: 3.0 }.into(),
+        mass_properties: (
+            RigidBodyMassPropsFlags::TRANSLATION_LOCKED_Y |
+            RigidBodyMassPropsFlags::ROTATION_LOCKED_X |
+            RigidBodyMassPropsFlags::ROTATION_LOCKED_Z
+        ).into(),
+        ..Default::default()
+    })
+    .insert_bundle(ColliderBundle {
+        shape: ColliderShape::cuboid(half_x, half_y, half_z).into(),
+        collider_type: ColliderType::Solid.into(),
+        material: ColliderMaterial { friction: 2.0, restitution: 0.9, ..Default::default() }.into(),
+        mass_properties: hull_mass(half_x, half_y, half_z).into(),
+        ..Default::default()
+    })
+    .insert(Transform::default())
+    .insert(RigidBodyPositionSync::Discrete)
+    .insert(RigidBodyTypeComponent::from(RigidBodyType::Dynamic))
+    // .insert(ColliderDebugRender::with_id(1))
+    .with_children(|ship| {
+        // Add ship model
+        let mut model = ship.spawn_bundle(PbrBundle {
+            transform: Transform::from_scale(Vec3::splat(archetype.model_scale)),
+            ..Default::default()
+        });
+        model.with_children(|model| {
+            model.spawn_scene(asset_server.load(archetype.model.as_str()));
+        });
+        if let Some((r, g, b)) = archetype.tint {
+            model.insert(Tint(Color::rgb(r, g, b)));
+        }
+    });
+    enemy.insert(Ship {
+        steering_wheel: SteeringWheel::default(),
+        health,
+        speed: archetype.speed,
+        last_hit: None
+    })
+    .insert(Sails::new(SailState::Half))
+    .insert(EnemyAi::new(enemy_counter.launched, position, health, Personality::random(&archetype.ai)));
+    if archetype.cannons > 0 {
+        enemy.insert(Cannon {
+            last_fired: 0.0,
+            cooldown: archetype.cannon_cooldown,
+            count: archetype.cannons,
+            accuracy: (archetype.accuracy * difficulty).min(1.0)
+        });
+    }
+    if archetype.ram_damage > 0 {
+        enemy.insert(Rammer { damage: archetype.ram_damage });
+    }
+}
+
+/// Colour to paint over a model's own once its scene has spawned
+#[derive(Component)]
+struct Tint(Color);
+
+/// Gives tinted models materials of their own, coloured by their tint. Scenes
+/// spawn a frame or more after the model entity, so a tint waits until its
+/// model's parts are there.
+fn model_tinting(
+    mut commands: Commands,
+    tinted: Query<(Entity, &Tint)>,
+    children: Query<&Children>,
+    mut parts: Query<&mut Handle<StandardMaterial>>,
+    mut materials: ResMut<Assets<StandardMaterial>>
+) {
+    for (entity, tint) in tinted.iter() {
+        let mut unvisited: Vec<Entity> = children.get(entity).map_or(Vec::new(), |c| c.to_vec());
+        let mut painted = false;
+        while let Some(part) = unvisited.pop() {
+            if let Ok(part_children) = children.get(part) {
+                unvisited.extend(part_children.iter().copied());
+            }
+            if let Ok(mut handle) = parts.get_mut(part) {
+                if let Some(material) = materials.get(&*handle) {
+                    let [r, g, b, a] = material.base_color.as_rgba_f32();
+                    let [tint_r, tint_g, tint_b, _] = tint.0.as_rgba_f32();
+                    let material = StandardMaterial {
+                        base_color: Color::rgba(r * tint_r, g * tint_g, b * tint_b, a),
+                        ..material.clone()
+                    };
+                    *handle = materials.add(material);
+                    painted = true;
+                }
+            }
+        }
+        if painted {
+            commands.entity(entity).remove::<Tint>();
+        }
+    }
+}
+
+/// Mass of a hull with the given half extents, centred towards the bow so
+/// the ship pivots about its stern
+fn hull_mass(half_x: f32, half_y: f32, half_z: f32) -> ColliderMassProps {
+    let mut mass = MassProperties::from_cuboid(HULL_DENSITY, Vec3::new(half_x, half_y, half_z).into());
+    mass.local_com = Vec3::new(0.0, 0.0, 1.0).into();
+    ColliderMassProps::MassProperties(Box::new(mass))
+}
+
 fn camera_setup(
     mut commands: Commands
 ) {
     let mut camera = OrthographicCameraBundle::new_3d();
     camera.orthographic_projection.scale = 20.0;
-    camera.transform = Transform::from_xyz(60.0, 60.0, 0.0).looking_at(Vec3::ZERO, Vec3::Y);
-    commands.spawn_bundle(camera);
+    camera.transform = camera::camera_transform(Vec3::ZERO);
+    // one camera keeps every player in view
+    commands.spawn_bundle(camera).insert(CameraRig::new(None));
+    commands.spawn_bundle(UiCameraBundle::default());
 }
 
 fn lighting_setup(
@@ -404,33 +826,37 @@ fn lighting_setup(
     });
 }
 
-#[derive(Default)]
+#[derive(Component, Default)]
 struct PreviousInput {
-    angle: f32
+    /// Stick angle last step, if it was pushed
+    angle: Option<f32>
 }
 
 fn player_input_handler(
-    gamepads: Res<Gamepads>,
-    button_inputs: Res<Input<GamepadButton>>,
-    // button_axes: Res<Axis<GamepadButton>>,
-    axes: Res<Axis<GamepadAxis>>,
-    mut prev_input: ResMut<PreviousInput>,
-    mut player_ships: Query<&mut Ship, With<Player>>,
-
+    player_actions: Res<PlayerActions>,
+    mut player_ships: Query<(&Player, &mut Ship, &mut PreviousInput)>,
+    config: Res<GameConfig>
 ) {
-    if let Some(mut player_ship) = player_ships.iter_mut().next() {
-        if let Some(gamepad) = gamepads.iter().next() {
-            let mut new_angle = prev_input.angle;
-            let left_stick_x = axes
-                .get(GamepadAxis(*gamepad, GamepadAxisType::LeftStickX))
-                .unwrap();
-            let left_stick_y = axes
-                .get(GamepadAxis(*gamepad, GamepadAxisType::LeftStickY))
-                .unwrap();
-            if left_stick_x.abs() > 0.5 || left_stick_y.abs() > 0.5 {
-                new_angle = left_stick_y.atan2(left_stick_x);
-            }
-            let delta_angle = new_angle - prev_input.angle;
+    for (player, mut player_ship, mut prev_input) in player_ships.iter_mut() {
+        let actions = player_actions.player(player.id);
+        let steering_left = actions.pressed(Action::SteerLeft);
+        let steering_right = actions.pressed(Action::SteerRight);
+        // Digital steering spins the wheel at a constant rate while held
+        if steering_left {
+            player_ship.steering_wheel.turn(DIGITAL_STEER_RATE);
+        }
+        if steering_right {
+            player_ship.steering_wheel.turn(-DIGITAL_STEER_RATE);
+        }
+        // When let go, the wheel spins back to the middle
+        if !steering_left && !steering_right && actions.steering_angle.is_none() {
+            let max_delta = config.rudder.return_rate * INPUT_STEP as f32;
+            player_ship.steering_wheel.turn_towards(0.0, max_delta);
+        }
+        // The wheel only follows the stick from where it was last step, so
+        // pushing it again after letting go doesn't jerk the wheel round
+        if let (Some(new_angle), Some(old_angle)) = (actions.steering_angle, prev_input.angle) {
+            let delta_angle = new_angle - old_angle;
             // Handle the cases where the delta crosses the PI boundary at 180 degrees
             let delta_angle = 
                 if delta_angle > consts::PI {
@@ -442,62 +868,196 @@ fn player_input_handler(
                 };
             
             player_ship.steering_wheel.turn(delta_angle);
-            prev_input.angle = new_angle;
+        }
+        prev_input.angle = actions.steering_angle;
+    }
+}
+
+/// Steps each player's sails up or down a state per press
+fn player_sail_handler(
+    player_actions: Res<PlayerActions>,
+    mut player_ships: Query<(&Player, &mut Sails)>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>
+) {
+    for (player, mut sails) in player_ships.iter_mut() {
+        let actions = player_actions.player(player.id);
+        let new_state =
+            if actions.just_pressed(Action::HoistSails) {
+                sails.state.raised()
+            } else if actions.just_pressed(Action::ReefSails) {
+                sails.state.lowered()
+            } else {
+                sails.state
+            };
+        if new_state != sails.state {
+            audio.play(asset_server.load("sounds/low.ogg"));
+            sails.state = new_state;
+        }
+    }
+}
+
+/// Somewhere in a (min, max) range, from 0 at the min to 1 at the max
+fn between((min, max): (f32, f32), fraction: f32) -> f32 {
+    min + (max - min) * fraction
+}
+
+/// Anywhere in a (min, max) range, picked at random
+fn random_in<T>((min, max): (T, T)) -> T
+where
+    T: Copy + Add<Output = T> + Sub<Output = T> + Mul<Output = T>,
+    Standard: Distribution<T>
+{
+    min + rand::random::<T>() * (max - min)
+}
+
+/// Rotation of a laser turret on its ship for a bearing. The beam fires out of
+/// the back of the model.
+fn turret_rotation(bearing: f32) -> Quat {
+    Quat::from_rotation_y(bearing + consts::PI)
+}
+
+/// Traverses each player's laser turret towards where they are aiming, as far
+/// as its arc allows. It holds its bearing while they aren't aiming.
+fn turret_aiming(
+    player_actions: Res<PlayerActions>,
+    mut lasers: Query<(&mut LaserGun, &mut Transform, &GlobalTransform, &Parent)>,
+    players: Query<(&Player, &GlobalTransform)>,
+    cameras: Query<(&Camera, &GlobalTransform), With<CameraRig>>,
+    windows: Res<Windows>,
+    time: Res<Time>,
+    config: Res<GameConfig>
+) {
+    for (mut laser_com, mut laser_t, laser_gt, parent) in lasers.iter_mut() {
+        let (player, ship_t) = match players.get(parent.0) {
+            Ok(player) => player,
+            Err(_) => continue
+        };
+        let aim_direction = match player_actions.player(player.id).aim {
+            Some(Aim::Stick(angle)) => Some(camera::screen_to_world(angle)),
+            Some(Aim::Cursor(cursor)) => cameras.iter().next()
+                .and_then(|(camera, camera_t)| camera::cursor_on_water(cursor, camera, camera_t, &windows))
+                .map(|point| (point - laser_gt.translation) * Vec3::new(1.0, 0.0, 1.0)),
+            None => None
+        };
+        if let Some(direction) = aim_direction {
+            let heading = ship_t.forward();
+            let bearing = heading.cross(direction).y.atan2(heading.dot(direction));
+            let (min_bearing, max_bearing) = config.turret.arc;
+            let max_delta = config.turret.traverse_rate * time.delta_seconds();
+            laser_com.bearing += (bearing.clamp(min_bearing, max_bearing) - laser_com.bearing)
+                .clamp(-max_delta, max_delta);
+            laser_t.rotation = turret_rotation(laser_com.bearing);
         }
     }
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn laser_gun_handler(
     mut commands: Commands,
-    gamepads: Res<Gamepads>,
-    button_axes: Res<Axis<GamepadButton>>,
-    mut lasers: Query<(Entity, &mut LaserGun, &GlobalTransform)>,
+    player_actions: Res<PlayerActions>,
+    mut lasers: Query<(&mut LaserGun, &GlobalTransform, &Parent)>,
     mut player_rb: Query<(
+        &Player,
         &mut RigidBodyVelocityComponent,
-        &RigidBodyMassPropsComponent
-    ), With<Player>>,
+        &RigidBodyMassPropsComponent,
+        &Buffs
+    )>,
     query_pipeline: Res<QueryPipeline>,
     collider_query: QueryPipelineColliderComponentsQuery,
-    mut lines: ResMut<DebugLines>,
+    clock: Res<GameClock>,
     time: Res<Time>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
-    mut enemy_counter: ResMut<EnemyCounter>,
-    enemies: Query<&Ship, Without<Player>>,
+    mut damage_events: EventWriter<DamageEvent>,
+    mut shake_events: EventWriter<ScreenShake>,
+    beam_targets: Query<(&GlobalTransform, Option<&Terrain>), (Or<(With<Ship>, With<Terrain>)>, Without<Player>)>,
     asset_server: Res<AssetServer>,
-    audio: Res<Audio>
+    audio: Res<Audio>,
+    config: Res<GameConfig>
 ) {
-    if let Some(gamepad) = gamepads.iter().next() {
-        if let Some((laser_ent, mut laser_com, laser_t)) = lasers.iter_mut().next() {
-            let now = time.seconds_since_startup();
-            let right_trigger = button_axes
-                .get(GamepadButton(*gamepad, GamepadButtonType::RightTrigger2))
-                .unwrap();
-            if right_trigger.abs() > 0.01 && now - laser_com.last_fired > LASER_COOLDOWN {
+    for (mut laser_com, laser_t, parent) in lasers.iter_mut() {
+        // each laser gun is fired by the player whose ship it is mounted on
+        if let Ok((player, mut rbv, rbmp, buffs)) = player_rb.get_mut(parent.0) {
+            let now = clock.elapsed;
+            let delta = time.delta_seconds();
+            let actions = player_actions.player(player.id);
+            let cooldown = config.laser_cooldown * buffs.factor(PowerUp::Capacitor, now, config.powerups.laser_cooldown);
+            let ready = !laser_com.overheated && now - laser_com.last_fired > cooldown;
+            let charging = ready && actions.pressed(Action::FireLaser);
+            if !charging {
+                laser_com.heat = (laser_com.heat - config.laser_cooling * delta).max(0.0);
+                if laser_com.overheated && laser_com.heat <= 0.0 {
+                    laser_com.overheated = false;
+                }
+            }
+            if charging {
+                // squeezing the trigger harder charges faster, and holding a
+                // charge keeps warming the gun
+                let charge_rate = actions.value(Action::FireLaser) / config.laser_charge_time;
+                laser_com.charge = (laser_com.charge + charge_rate * delta).min(1.0);
+                laser_com.heat += config.laser_holding_heat * delta;
+                if laser_com.heat >= 1.0 {
+                    laser_com.overheated = true;
+                    laser_com.charge = 0.0;
+                }
+            } else if ready && laser_com.charge > 0.0 {
+                // fire the laser on release
+                let charge = laser_com.charge;
+                laser_com.charge = 0.0;
                 laser_com.last_fired = now;
-                // fire the laser
+                laser_com.heat += between(config.laser_heat, charge);
+                if laser_com.heat >= 1.0 {
+                    laser_com.overheated = true;
+                }
+                let range = between(config.laser_range, charge);
+                let (min_damage, max_damage) = config.laser_damage;
+                let damage = min_damage + ((max_damage - min_damage) as f32 * charge).round() as i32;
+                let width = between(config.laser_width, charge);
+                let origin = laser_t.translation + laser_t.forward()*-2.0;
+                let direction = laser_t.forward() * -1.0;
                 let collider_set = QueryPipelineColliderComponentsSet(&collider_query);
-                let shape = Ball::new(1.0);
-                let shape_pos = (laser_t.translation + laser_t.forward()*-2.0, Quat::from_rotation_x(0.4)).into();
-                let shape_vel = (laser_t.forward() * -1.0).into();
-                let max_toi = 50.0;
                 let groups = InteractionGroups::all();
-                let filter = None;
-
-                // laser effect
-                commands.entity(laser_ent).with_children(|parent| {
-                    parent.spawn_bundle(PbrBundle {
-                        transform: Transform::from_rotation(Quat::from_rotation_x(consts::FRAC_PI_2)),
-                        mesh: meshes.add(Mesh::from(bevy::prelude::shape::Capsule {
-                            radius: 0.1,
-                            rings: 1,
-                            depth: 49.0,
-                            ..Default::default()
-                        })),
-                        material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
-                        ..Default::default()
-                    }).insert(Laser {fired: now});
+
+                // the beam burns through ships but stops at the first island, rock or reef
+                let is_terrain = |handle: ColliderHandle| {
+                    beam_targets.get(handle.entity()).is_ok_and(|(_, terrain)| terrain.is_some())
+                };
+                let reach = query_pipeline.cast_shape(
+                    &collider_set,
+                    &(origin, Quat::IDENTITY).into(),
+                    &direction.into(),
+                    &Ball::new(width),
+                    range,
+                    groups,
+                    Some(&is_terrain)
+                ).map_or(range, |(_, hit)| hit.toi);
+                let beam = Capsule::new_y(reach / 2.0, width);
+                let beam_pos = (origin + direction * reach / 2.0, Quat::from_rotation_arc(Vec3::Y, direction)).into();
+                // every enemy along the beam, nearest first
+                let mut pierced: Vec<(Entity, f32)> = Vec::new();
+                query_pipeline.intersections_with_shape(&collider_set, &beam_pos, &beam, groups, None, |handle| {
+                    if let Ok((enemy_t, None)) = beam_targets.get(handle.entity()) {
+                        pierced.push((handle.entity(), (enemy_t.translation - origin).dot(direction)));
+                    }
+                    true
                 });
+                pierced.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+
+                // laser effect, the same capsule the shot sweeps. It isn't parented to the
+                // gun, so it stays where it was fired and the gun's scale doesn't stretch it.
+                commands.spawn_bundle(PbrBundle {
+                    transform: Transform::from_translation(origin + direction * reach / 2.0)
+                        .with_rotation(Quat::from_rotation_arc(Vec3::Y, direction)),
+                    mesh: meshes.add(Mesh::from(bevy::prelude::shape::Capsule {
+                        radius: width,
+                        rings: 1,
+                        depth: reach,
+                        ..Default::default()
+                    })),
+                    material: materials.add(Color::rgb(1.0, 0.0, 0.0).into()),
+                    ..Default::default()
+                }).insert(Laser {fired: now});
 
                 // audio
                 let low_boom = asset_server.load("sounds/low.ogg");
@@ -506,17 +1066,18 @@ fn laser_gun_handler(
                 audio.play(laser);
 
                 // recoil
-                if let Some((mut rbv, rbmp)) = player_rb.iter_mut().next() {
-                    rbv.apply_impulse(rbmp, (laser_t.forward() * 10000.0).into());
-                }
+                rbv.apply_impulse(rbmp, (direction * -between(config.laser_recoil, charge)).into());
+                shake_events.send(ScreenShake { player: player.id, amount: LASER_SHAKE * (1.0 + charge) });
 
-                if let Some((handle, hit)) = query_pipeline.cast_shape(
-                    &collider_set, &shape_pos, &shape_vel, &shape, max_toi, groups, filter
-                ) {
-                    if let Ok(_) = enemies.get(handle.entity()) {
-                        commands.entity(handle.entity()).despawn_recursive();
-                        enemy_counter.dead += 1;
-                    }
+                // each ship pierced takes less damage than the one before it
+                for (pierce, (target, _)) in pierced.iter().enumerate() {
+                    let falloff = config.laser_pierce_falloff.powi(pierce as i32);
+                    damage_events.send(DamageEvent {
+                        source: Some(parent.0),
+                        target: *target,
+                        amount: (damage as f32 * falloff).round() as i32,
+                        kind: DamageKind::Laser
+                    });
                 }
             }
         }
@@ -525,181 +1086,53 @@ fn laser_gun_handler(
 
 fn laser_cleanup(
     mut commands: Commands,
-    time: Res<Time>,
-    mut lasers: Query<(Entity, &Laser, &mut Transform)>
+    clock: Res<GameClock>,
+    mut lasers: Query<(Entity, &Laser, &mut Transform)>,
+    config: Res<GameConfig>
 ) {
-    let now = time.seconds_since_startup();
+    let now = clock.elapsed;
     for (ent, laser, mut t) in lasers.iter_mut() {
         let since_fired = now - laser.fired;
-        if since_fired > LASER_TIMEOUT {
+        if since_fired > config.laser_timeout {
             commands.entity(ent).despawn_recursive();
         } else {
-            let girth = (since_fired / LASER_TIMEOUT) as f32;
-            t.scale = Vec3::new(0.0f32.max(t.scale.x * girth), 1.0, 0.0f32.max(t.scale.x * girth));
+            // the beam thins out along its length until it is gone
+            let girth = 1.0 - (since_fired / config.laser_timeout) as f32;
+            t.scale = Vec3::new(girth, 1.0, girth);
         }
     }
 }
 
+#[allow(clippy::type_complexity)]
 fn ship_movement(
-    mut ships: Query<(
-        &Ship,
-        &Transform,
-        &mut RigidBodyForcesComponent,
-        &mut RigidBodyMassPropsComponent
-    )>,
-) {
-    for (ship, t, mut rbf, mut rbmp) in ships.iter_mut() {
-        let centre_of_rotation = t.translation + t.left() * (ship.steering_wheel.angle / 4.0);
-        let lever_arm_vector = t.translation - centre_of_rotation;
-        let torque = lever_arm_vector.cross(t.forward()) * 1000.0;
-        rbmp.local_mprops.local_com = Vec3::new(0.0, 0.0, 1.0).into();
-        rbf.force = (t.forward()*3000.0).into();
-        rbf.torque = torque.into();
-    }
-}
-
-fn enemy_movement_ai(
-    mut commands: Commands,
-    mut enemy_ships: Query<(Entity, &mut Ship, &Transform), Without<Player>>,
-    mut player_ts: Query<&Transform, With<Player>>,
-    mut lines: ResMut<DebugLines>,
-    mut enemy_counter: ResMut<EnemyCounter>
-) {
-    // Try and move into range of the player
-    if let Some(player_t) = player_ts.iter().next() {
-        for (enemy_ent, mut enemy_ship, t) in enemy_ships.iter_mut() {
-            if enemy_ship.health <= 0 {
-                commands.entity(enemy_ent).despawn_recursive();
-                enemy_counter.dead += 1;
-            };
-            let vec_to_player = player_t.translation - t.translation;
-            let angle_to_player =
-                t.forward().angle_between(vec_to_player);
-            if is_to_left_of_player(player_t, t) {
-                enemy_ship.steering_wheel.angle = angle_to_player * 6.0;
-            } else {
-                enemy_ship.steering_wheel.angle = angle_to_player * -6.0;
-            }
-            // lines.line(
-            //     t.translation,
-            //     t.translation + t.forward() * 10.0,
-            //     0.0
-            // );
-        }
-    }
-}
-
-fn cannon_ai(
-    mut commands: Commands,
-    mut player_ts: Query<&Transform, With<Player>>,
-    mut cannons: Query<(&mut Cannon, &Transform), Without<Player>>,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>,
+    mut ships: Query<(&mut Ship, Option<&Sails>, Option<&Buffs>, &Transform, &mut RigidBodyForcesComponent)>,
+    wind: Res<Wind>,
     time: Res<Time>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
 ) {
-    if let Some(player_t) = player_ts.iter().next() {
-        let now = time.seconds_since_startup();
-        for (mut cannon, t) in cannons.iter_mut() {
-            let to_player = player_t.translation - t.translation;
-            let angle = t.forward().angle_between(to_player);
-            if
-                // cannon is off cooldown
-                now - cannon.last_fired > CANNON_COOLDOWN // &&
-                // // enemy is in range
-                // t.translation.length() <= ENEMY_CANNON_RANGE &&
-                // // player is either directly to left or right of enemy
-                // angle > consts::FRAC_PI_2 - 0.3 &&
-                // angle < consts::FRAC_PI_2 + 0.3
-            {
-                if is_to_left_of_player(player_t, t) {
-                    // fire to the left
-                    fire_cannon(&mut commands, t, t.left(), &asset_server, &audio);
-                } else {
-                    // fire to the right
-                    fire_cannon(&mut commands, t, t.right(), &asset_server, &audio);
-                }
-                cannon.last_fired = time.seconds_since_startup();
-            }
-        }
+    for (mut ship, sails, buffs, t, mut rbf) in ships.iter_mut() {
+        ship.steering_wheel.swing_rudder(config.rudder.swing_rate * time.delta_seconds());
+        // positive turning is to port, anticlockwise seen from above
+        let torque = Vec3::Y * ship.steering_wheel.turning(config.rudder.curve) * config.ship_torque * ship.speed;
+        // ships without sails, like the mothership, are under power
+        let drive = sails.map_or(1.0, |sails| sails.drive(&wind, t.forward()));
+        let boost = buffs.map_or(1.0, |buffs| {
+            buffs.factor(PowerUp::SpeedBoost, clock.elapsed, config.powerups.speed) as f32
+        });
+        rbf.force = (t.forward()*config.ship_thrust*ship.speed*drive*boost).into();
+        rbf.torque = torque.into();
     }
 }
 
-fn fire_cannon(
-    commands: &mut Commands,
-    enemy_transform: &Transform,
-    direction: Vec3,
-    asset_server: &Res<AssetServer>,
-    audio: &Res<Audio>
-) {
-    let cannon = asset_server.load("sounds/cannon.ogg");
-    audio.play(cannon);
-    let cannonball = asset_server.load("models/pirate/cannonball.glb#Scene0");
-    // commands.spawn_bundle(PbrBundle {
-    //     transform: t,
-    //     ..Default::default()
-    commands.spawn_bundle(RigidBodyBundle {
-        position: (enemy_transform.translation + direction * 2.0 + enemy_transform.up() * 2.0).into(),
-        velocity: RigidBodyVelocity { 
-            linvel: (direction * 5.0).into(),
-            ..Default::default()
-        }.into(),
-        forces: RigidBodyForces {
-            gravity_scale: 0.1,
-            ..Default::default()
-        }.into(),
-        ..Default::default()
-    })
-    .insert_bundle(ColliderBundle {
-        shape: ColliderShape::ball(0.5).into(),
-        collider_type: ColliderType::Solid.into(),
-        material: ColliderMaterial { friction: 0.7, restitution: 0.1, ..Default::default() }.into(),
-        mass_properties: ColliderMassProps::Density(100.0).into(),
-        ..Default::default()
+fn nearest_player<'a>(
+    player_ts: impl Iterator<Item = &'a Transform>,
+    position: Vec3
+) -> Option<&'a Transform> {
+    player_ts.min_by(|a, b| {
+        a.translation.distance_squared(position)
+            .total_cmp(&b.translation.distance_squared(position))
     })
-    .with_children(|parent| {
-        parent.spawn_scene(cannonball);
-    })
-    .insert(Transform::default())
-    .insert(RigidBodyPositionSync::Discrete)
-    .insert(RigidBodyTypeComponent::from(RigidBodyType::Dynamic))
-    .insert(Cannonball);
-}
-
-fn cannonball_tracking(
-    mut commands: Commands,
-    mut cannonballs: Query<(Entity, &Transform), With<Cannonball>>,
-    mut ships: Query<(Entity, &mut Ship), With<Player>>,
-    mut contact_events: EventReader<ContactEvent>,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>
-) {
-    for (entity, t) in cannonballs.iter() {
-        // cannonball drops into the sea
-        if t.translation.y < 0.0 {
-            commands.entity(entity).despawn_recursive();
-        }
-    }
-    for contact_event in contact_events.iter() {
-        match contact_event {
-            ContactEvent::Started(h1, h2) => {
-                let explosion = asset_server.load("sounds/explosion_1.ogg");
-                audio.play(explosion);
-                if let Ok((cb_entity, _cb_t)) = cannonballs.get_mut(h1.entity()) {
-                    commands.entity(cb_entity).despawn_recursive();
-                }
-                else if let Ok((_ship_ent, mut ship)) = ships.get_mut(h1.entity()) {
-                    ship.health -= 10;
-                }
-                if let Ok((cb_entity, _cb_t)) = cannonballs.get_mut(h2.entity()) {
-                    commands.entity(cb_entity).despawn_recursive();
-                }
-                else if let Ok((_ship_ent, mut ship)) = ships.get_mut(h2.entity()) {
-                    ship.health -= 10;
-                }
-            },
-            _ => ()
-        };
-    }
 }
 
 fn is_to_left_of_player(
@@ -711,88 +1144,164 @@ fn is_to_left_of_player(
     to_player.dot(right) < 0.0
 }
 
+#[allow(clippy::too_many_arguments)]
 fn hud_handler(
-    mut text_query: Query<&mut Text, With<HUD>>,
-    player: Query<&Ship, With<Player>>,
-    enemy_counter: Res<EnemyCounter>
+    mut text_query: Query<&mut Text, With<Hud>>,
+    players: Query<(Entity, &Player, &Ship, &Sails, &Buffs)>,
+    lasers: Query<(&LaserGun, &Parent)>,
+    player_count: Res<PlayerCount>,
+    enemy_counter: Res<EnemyCounter>,
+    waves: Res<WaveList>,
+    wave_state: Res<WaveState>,
+    boss_fight: Res<BossFight>,
+    score: Res<Score>,
+    wind: Res<Wind>,
+    clock: Res<GameClock>
 ) {
-    if let Some(player) = player.iter().next() {
-        if let Some(mut text_box) = text_query.iter_mut().next() {
-            text_box.sections[0].value = format!("health: {}\nenemies left: {}", player.health, ENEMY_COUNT - enemy_counter.dead);
+    if let Some(mut text_box) = text_query.iter_mut().next() {
+        let mut text = format!("score: {}\n", score.points);
+        for id in 0..player_count.0 {
+            let status = players.iter()
+                .find(|(_, player, _, _, _)| player.id == id)
+                .map_or("sunk".to_string(), |(ent, _, ship, sails, buffs)| {
+                    let laser = lasers.iter()
+                        .find(|(_, parent)| parent.0 == ent)
+                        .map_or(String::new(), |(laser, _)| {
+                            let heat = if laser.overheated { "overheated!".to_string() } else { meter(laser.heat) };
+                            format!("\nlaser: {}  heat: {}", meter(laser.charge), heat)
+                        });
+                    let power_ups: Vec<String> = buffs.remaining(clock.elapsed).iter()
+                        .map(|(kind, left)| format!("{} {}", kind.name(), left.ceil()))
+                        .collect();
+                    let power_ups =
+                        if power_ups.is_empty() {
+                            String::new()
+                        } else {
+                            format!("\npower-ups: {}", power_ups.join("  "))
+                        };
+                    format!(
+                        "{}  sails: {}\nhelm: {}{}{}",
+                        ship.health, sails.state.name(), rudder_gauge(ship.steering_wheel.rudder), laser, power_ups
+                    )
+                });
+            if player_count.0 == 1 {
+                text += &format!("health: {}\n", status);
+            } else {
+                text += &format!("player {} health: {}\n", id + 1, status);
+            }
+        }
+        text += &format!("wind: {} {}\n", wind.arrow(), wind.description());
+        if waves.endless {
+            text += &format!("wave: {}\n", wave_state.number + 1);
+        } else {
+            text += &format!("wave: {}/{}\n", wave_state.number + 1, waves.waves.len());
+        }
+        if boss_fight.arrived {
+            text += "the mothership is here!";
+        } else if let Some(ends) = wave_state.intermission_ends {
+            let remaining = (ends - clock.elapsed).max(0.0).ceil();
+            text += &format!("next wave in: {}", remaining);
+        } else {
+            text += &format!("enemies left: {}", enemy_counter.total - enemy_counter.dead);
         }
+        text_box.sections[0].value = text;
     }
 }
 
-fn teardown(mut commands: Commands, entities: Query<Entity, Without<Camera>>) {
+/// Bar showing how far the rudder is over, with port on the left
+fn rudder_gauge(rudder: f32) -> String {
+    const HALF_WIDTH: i32 = 5;
+    let over = (rudder * HALF_WIDTH as f32).round() as i32;
+    (-HALF_WIDTH..=HALF_WIDTH)
+        .map(|cell| {
+            // cells to port count up from the middle going left
+            let cell = -cell;
+            if cell == 0 {
+                '|'
+            } else if (1..=over).contains(&cell) || (over..=-1).contains(&cell) {
+                '■'
+            } else {
+                '·'
+            }
+        })
+        .collect()
+}
+
+/// Bar filled in proportion to a fraction from 0 to 1
+fn meter(fraction: f32) -> String {
+    const WIDTH: usize = 5;
+    let filled = (fraction.clamp(0.0, 1.0) * WIDTH as f32).round() as usize;
+    "■".repeat(filled) + &"·".repeat(WIDTH - filled)
+}
+
+/// Kept between rounds, unlike everything spawned while playing
+#[derive(Component)]
+struct Persistent;
+
+/// Marks everything that exists once startup is done, such as cameras and
+/// the debug line meshes, so teardown leaves it alone
+fn mark_persistent(mut commands: Commands, entities: Query<Entity>) {
+    for entity in entities.iter() {
+        commands.entity(entity).insert(Persistent);
+    }
+}
+
+fn teardown(mut commands: Commands, entities: Query<Entity, Without<Persistent>>) {
     for entity in entities.iter() {
         commands.entity(entity).despawn_recursive();
     }
 }
 
 fn game_over_checker(
-    mut commands: Commands,
-    player: Query<(Entity, &Ship, &GlobalTransform), With<Player>>,
-    enemy_counter: Res<EnemyCounter>,
+    player: Query<&Player>,
+    mut sunk_events: EventReader<PlayerSunk>,
+    boss_fight: Res<BossFight>,
     mut state: ResMut<State<GameState>>,
     mut text_query: Query<&mut Text, With<GameOverText>>,
-    asset_server: Res<AssetServer>,
-    audio: Res<Audio>
+    bindings: Res<InputBindings>,
+    scheme: Res<ControlScheme>
 ) {
     let mut gameover = false;
-    if let Some((ent, ship, gt)) = player.iter().next() {
-        // if player out of bounds
-        if gt.translation.x < -30.0 || gt.translation.x > 40.0 || gt.translation.z > 30.0 || gt.translation.z < -30.0 {
-            gameover = true;
-            if let Some(mut text) = text_query.iter_mut().next() {
-                text.sections[0].value = "You got lost at sea.\nPress left trigger to try again.".to_string()
-            }
-            let explosion = asset_server.load("sounds/explosion_2.ogg");
-            audio.play(explosion);
-            commands.entity(ent).despawn_recursive();
-        }
-        // if player health is out
-        if ship.health <= 0 {
+    let prompt = bindings.prompt(Action::Restart, *scheme);
+    let players_left = player.iter().count();
+    if let Some(sunk) = sunk_events.iter().last() {
+        let message = match sunk.kind {
+            DamageKind::LostAtSea => "You got lost at sea.",
+            _ => "Your ship got destroyed."
+        };
+        // the round only ends once every player has gone down
+        if players_left == 0 {
             gameover = true;
             if let Some(mut text) = text_query.iter_mut().next() {
-                text.sections[0].value = "Your ship got destroyed.\nPress left trigger to try again.".to_string()
+                text.sections[0].value = format!("{}\nPress {} to try again.", message, prompt)
             }
-            let explosion = asset_server.load("sounds/explosion_2.ogg");
-            audio.play(explosion);
-            commands.entity(ent).despawn_recursive();
         }
-        // if player defeated all enemies
-        if enemy_counter.dead == ENEMY_COUNT {
-            gameover = true;
-            if let Some(mut text) = text_query.iter_mut().next() {
-                text.sections[0].value = "You made it out alive! Well done!\nPress left trigger to play again.".to_string()
-            }
+    }
+    // if players brought down the mothership
+    if players_left > 0 && boss_fight.defeated {
+        gameover = true;
+        if let Some(mut text) = text_query.iter_mut().next() {
+            text.sections[0].value = format!("The mothership is sunk! Well done!\nPress {} to play again.", prompt)
         }
     }
     if gameover {
-        match state.set(GameState::GameOver) {
-            _ => ()
-        };
+        let _ = state.set(GameState::GameOver);
     }
 }
 
 fn game_over(
     mut state: ResMut<State<GameState>>,
-    button_axes: Res<Axis<GamepadButton>>,
-    gamepads: Res<Gamepads>,
+    player_actions: Res<PlayerActions>,
+    name_entry: Res<NameEntry>
 ) {
-    for gamepad in gamepads.iter() {
-        let right_trigger = button_axes
-            .get(GamepadButton(*gamepad, GamepadButtonType::LeftTrigger2))
-            .unwrap();
-        if right_trigger.abs() > 0.01 {
-            state.set(GameState::Running).unwrap();
-        }
+    // wait until a new high score has been given a name
+    if !name_entry.active && player_actions.any_just_pressed(Action::Restart) {
+        let _ = state.set(GameState::Running);
     }
 }
 
 // TODO
 // - sound effects and music
-// - wheel animation
 
 // -- submit --
 
@@ -0,0 +1,256 @@
+use bevy::{
+    prelude::*, app::AppExit
+};
+use bevy_rapier3d::prelude::*;
+
+use crate::{configs_loaded, GameState};
+use crate::input::{Action, InputBindings, PlayerActions};
+use crate::islands::SeaSeed;
+use crate::scoring::HighScores;
+use crate::waves::WaveList;
+
+const MENU_STATES: [GameState; 4] = [
+    GameState::MainMenu,
+    GameState::Paused,
+    GameState::Settings,
+    GameState::HighScores
+];
+
+/// Spawns a navigable menu whenever one of the menu states is on top of the stack
+pub struct MenuPlugin;
+
+impl Plugin for MenuPlugin {
+    fn build(&self, app: &mut App) {
+        for state in MENU_STATES {
+            app.add_system_set(SystemSet::on_enter(state.clone()).with_system(menu_setup))
+                .add_system_set(SystemSet::on_resume(state.clone()).with_system(menu_setup))
+                .add_system_set(SystemSet::on_pause(state.clone()).with_system(menu_cleanup))
+                .add_system_set(SystemSet::on_exit(state).with_system(menu_cleanup));
+        }
+        app.add_system_set(SystemSet::on_update(GameState::Running).with_system(pause_handler))
+            .add_system_set(SystemSet::on_enter(GameState::Paused).with_system(freeze_physics))
+            .add_system_set(SystemSet::on_exit(GameState::Paused).with_system(unfreeze_physics))
+            .add_system(menu_navigation.with_run_criteria(configs_loaded).label(MenuNavigation))
+            .add_system(menu_display.with_run_criteria(configs_loaded).after(MenuNavigation));
+    }
+}
+
+#[derive(SystemLabel, Debug, Hash, PartialEq, Eq, Clone)]
+struct MenuNavigation;
+
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+enum MenuItem {
+    Play,
+    HighScores,
+    Settings,
+    Quit,
+    Resume,
+    QuitToMenu,
+    Layout,
+    Endless,
+    Sea,
+    Back
+}
+
+impl MenuItem {
+    fn label(&self, bindings: &InputBindings, waves: &WaveList, sea_seed: &SeaSeed) -> String {
+        match self {
+            MenuItem::Play => "Set sail".to_string(),
+            MenuItem::HighScores => "High scores".to_string(),
+            MenuItem::Settings => "Settings".to_string(),
+            MenuItem::Quit => "Quit".to_string(),
+            MenuItem::Resume => "Resume".to_string(),
+            MenuItem::QuitToMenu => "Abandon ship".to_string(),
+            MenuItem::Layout => format!(
+                "Gamepad layout: {}",
+                if bindings.left_handed() { "left handed" } else { "right handed" }
+            ),
+            MenuItem::Endless => format!(
+                "Endless waves: {}",
+                if waves.endless { "on" } else { "off" }
+            ),
+            MenuItem::Sea => match sea_seed.fixed {
+                Some(seed) => format!("Sea: {}", seed),
+                None => "Sea: new every round".to_string()
+            },
+            MenuItem::Back => "Back".to_string()
+        }
+    }
+}
+
+#[derive(Component)]
+struct Menu {
+    state: GameState,
+    items: Vec<MenuItem>,
+    selected: usize
+}
+
+fn menu_setup(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    state: Res<State<GameState>>
+) {
+    let items = match state.current() {
+        GameState::MainMenu => vec![MenuItem::Play, MenuItem::HighScores, MenuItem::Settings, MenuItem::Quit],
+        GameState::Paused => vec![MenuItem::Resume, MenuItem::Settings, MenuItem::QuitToMenu],
+        GameState::Settings => vec![MenuItem::Layout, MenuItem::Endless, MenuItem::Sea, MenuItem::Back],
+        GameState::HighScores => vec![MenuItem::Back],
+        _ => return
+    };
+    let font = asset_server.load("fonts/Arial Unicode.ttf");
+    commands.spawn_bundle(TextBundle {
+        style: Style {
+            align_self: AlignSelf::FlexEnd,
+            position_type: PositionType::Absolute,
+            position: Rect {
+                top: Val::Px(150.0),
+                left: Val::Px(100.0),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        text: Text::with_section(
+            "",
+            TextStyle {
+                font,
+                font_size: 50.0,
+                color: Color::WHITE,
+            },
+            Default::default(),
+        ),
+        ..Default::default()
+    })
+    .insert(Menu {
+        state: state.current().clone(),
+        items,
+        selected: 0
+    });
+}
+
+fn menu_cleanup(
+    mut commands: Commands,
+    menus: Query<Entity, With<Menu>>
+) {
+    for menu in menus.iter() {
+        commands.entity(menu).despawn_recursive();
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn menu_navigation(
+    mut menus: Query<&mut Menu>,
+    mut player_actions: ResMut<PlayerActions>,
+    mut state: ResMut<State<GameState>>,
+    mut bindings: ResMut<InputBindings>,
+    mut waves: ResMut<WaveList>,
+    mut sea_seed: ResMut<SeaSeed>,
+    mut characters: EventReader<ReceivedCharacter>,
+    mut exit: EventWriter<AppExit>
+) {
+    let mut menu = match menus.iter_mut().find(|menu| menu.state == *state.current()) {
+        Some(menu) => menu,
+        None => return
+    };
+    // a seed is typed in with the sea selected
+    for character in characters.iter() {
+        if menu.items[menu.selected] != MenuItem::Sea {
+            continue;
+        }
+        match character.char {
+            '\u{8}' => sea_seed.pop_digit(),
+            digit => if let Some(digit) = digit.to_digit(10) {
+                sea_seed.push_digit(digit as u64);
+            }
+        }
+    }
+    let count = menu.items.len();
+    if player_actions.any_just_pressed(Action::MenuUp) {
+        menu.selected = (menu.selected + count - 1) % count;
+    }
+    if player_actions.any_just_pressed(Action::MenuDown) {
+        menu.selected = (menu.selected + 1) % count;
+    }
+    let resume = *state.current() == GameState::Paused && player_actions.any_just_pressed(Action::Pause);
+    let back = *state.current() != GameState::MainMenu && player_actions.any_just_pressed(Action::Back);
+    let chosen =
+        if resume || back {
+            Some(MenuItem::Back)
+        } else if player_actions.any_just_pressed(Action::Confirm) {
+            Some(menu.items[menu.selected])
+        } else {
+            None
+        };
+    let result = match chosen {
+        Some(MenuItem::Play) => state.set(GameState::Running),
+        Some(MenuItem::HighScores) => state.push(GameState::HighScores),
+        Some(MenuItem::Settings) => state.push(GameState::Settings),
+        Some(MenuItem::Quit) => {
+            exit.send(AppExit);
+            Ok(())
+        },
+        Some(MenuItem::Resume) | Some(MenuItem::Back) => state.pop(),
+        Some(MenuItem::QuitToMenu) => state.replace(GameState::MainMenu),
+        Some(MenuItem::Layout) => {
+            bindings.mirror();
+            Ok(())
+        },
+        Some(MenuItem::Endless) => {
+            waves.endless = !waves.endless;
+            Ok(())
+        },
+        // switch between replaying the latest sea and a new one every round
+        Some(MenuItem::Sea) => {
+            sea_seed.fixed = match sea_seed.fixed {
+                Some(_) => None,
+                None => Some(sea_seed.current)
+            };
+            Ok(())
+        },
+        None => return
+    };
+    if result.is_ok() {
+        player_actions.consume();
+    }
+}
+
+fn menu_display(
+    mut menus: Query<(&Menu, &mut Text)>,
+    bindings: Res<InputBindings>,
+    waves: Res<WaveList>,
+    sea_seed: Res<SeaSeed>,
+    high_scores: Res<HighScores>
+) {
+    for (menu, mut text) in menus.iter_mut() {
+        let mut value = match menu.state {
+            GameState::MainMenu => "Yo ho ho and an\nextra-terrestrial gun!\n\n".to_string(),
+            GameState::Paused => format!("Paused\nSea {}\n\n", sea_seed.current),
+            GameState::Settings => "Settings\n\n".to_string(),
+            GameState::HighScores => format!("{}\n", high_scores.table()),
+            _ => String::new()
+        };
+        for (i, item) in menu.items.iter().enumerate() {
+            let marker = if i == menu.selected { "> " } else { "  " };
+            value += &format!("{}{}\n", marker, item.label(&bindings, &waves, &sea_seed));
+        }
+        text.sections[0].value = value;
+    }
+}
+
+fn pause_handler(
+    mut player_actions: ResMut<PlayerActions>,
+    mut state: ResMut<State<GameState>>
+) {
+    if player_actions.any_just_pressed(Action::Pause) && state.push(GameState::Paused).is_ok() {
+        player_actions.consume();
+    }
+}
+
+fn freeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = false;
+    rapier_config.query_pipeline_active = false;
+}
+
+fn unfreeze_physics(mut rapier_config: ResMut<RapierConfiguration>) {
+    rapier_config.physics_pipeline_active = true;
+    rapier_config.query_pipeline_active = true;
+}
@@ -0,0 +1,213 @@
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::*;
+
+use std::collections::{HashMap, HashSet};
+
+use crate::{random_in, GameClock, Player, Ship};
+use crate::config::GameConfig;
+use crate::islands::Terrain;
+
+// Places tried for each crate before waiting for the next one
+const PLACEMENT_TRIES: u32 = 10;
+// Open water left between a crate and the shore
+const TERRAIN_CLEARANCE: f32 = 3.0;
+const CRATE_SIZE: f32 = 1.2;
+// How far crates bob up and down on the swell, and how many times a second
+const BOB_HEIGHT: f32 = 0.2;
+const BOB_RATE: f64 = 0.5;
+
+#[derive(Clone, Copy, Eq, PartialEq, Hash, Debug)]
+pub enum PowerUp {
+    /// Patches up the hull straight away
+    Repair,
+    /// Cuts the laser's cooldown
+    Capacitor,
+    /// Turns aside all harm but the sea's
+    Shield,
+    /// Reloads the broadsides faster
+    RapidFire,
+    SpeedBoost
+}
+
+const POWER_UPS: [PowerUp; 5] = [
+    PowerUp::Repair,
+    PowerUp::Capacitor,
+    PowerUp::Shield,
+    PowerUp::RapidFire,
+    PowerUp::SpeedBoost
+];
+
+impl PowerUp {
+    fn colour(&self) -> Color {
+        match self {
+            PowerUp::Repair => Color::rgb(0.2, 0.8, 0.3),
+            PowerUp::Capacitor => Color::rgb(1.0, 0.2, 0.2),
+            PowerUp::Shield => Color::rgb(0.3, 0.6, 1.0),
+            PowerUp::RapidFire => Color::rgb(1.0, 0.6, 0.1),
+            PowerUp::SpeedBoost => Color::rgb(1.0, 1.0, 0.3)
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PowerUp::Repair => "repair",
+            PowerUp::Capacitor => "capacitor",
+            PowerUp::Shield => "shield",
+            PowerUp::RapidFire => "rapid fire",
+            PowerUp::SpeedBoost => "speed boost"
+        }
+    }
+}
+
+/// A crate floating in the sea, for the first player to sail into
+#[derive(Component)]
+pub struct Pickup {
+    kind: PowerUp,
+    spawned: f64
+}
+
+/// Timed power-ups a ship has picked up, and when each wears off
+#[derive(Component, Default)]
+pub struct Buffs {
+    expires: HashMap<PowerUp, f64>
+}
+
+impl Buffs {
+    pub fn active(&self, kind: PowerUp, now: f64) -> bool {
+        self.expires.get(&kind).is_some_and(|expires| *expires > now)
+    }
+
+    /// Power-ups still running with the seconds each has left, in a steady order
+    pub fn remaining(&self, now: f64) -> Vec<(PowerUp, f64)> {
+        POWER_UPS.iter()
+            .filter_map(|kind| self.expires.get(kind).map(|expires| (*kind, expires - now)))
+            .filter(|(_, left)| *left > 0.0)
+            .collect()
+    }
+
+    /// Multiplier for when a power-up is running, or 1 when it isn't
+    pub fn factor(&self, kind: PowerUp, now: f64, factor: f64) -> f64 {
+        if self.active(kind, now) { factor } else { 1.0 }
+    }
+}
+
+/// When the next crate floats up
+#[derive(Default)]
+pub struct PickupSpawner {
+    next: f64
+}
+
+pub fn pickup_setup(
+    mut spawner: ResMut<PickupSpawner>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    spawner.next = clock.elapsed + random_in(config.powerups.spawn_interval);
+}
+
+/// Floats up a crate of a random power-up every so often, somewhere in open water
+#[allow(clippy::too_many_arguments)]
+pub fn pickup_spawner(
+    mut commands: Commands,
+    mut spawner: ResMut<PickupSpawner>,
+    pickups: Query<&Pickup>,
+    terrain: Query<&Transform, With<Terrain>>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    mut materials: ResMut<Assets<StandardMaterial>>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    let now = clock.elapsed;
+    if now < spawner.next {
+        return;
+    }
+    spawner.next = now + random_in(config.powerups.spawn_interval);
+    if pickups.iter().count() >= config.powerups.max_afloat {
+        return;
+    }
+    let arena = config.arena.bounds;
+    let spot = (0..PLACEMENT_TRIES)
+        .map(|_| Vec3::new(random_in((arena.min_x, arena.max_x)), 0.0, random_in((arena.min_z, arena.max_z))))
+        // obstacles are scaled to their radius
+        .find(|spot| {
+            terrain.iter().all(|t| {
+                t.translation.distance(*spot) > t.scale.x + TERRAIN_CLEARANCE
+            })
+        });
+    let spot = match spot {
+        Some(spot) => spot,
+        None => return
+    };
+    let kind = POWER_UPS[rand::random::<usize>() % POWER_UPS.len()];
+    commands.spawn_bundle(PbrBundle {
+        mesh: meshes.add(Mesh::from(bevy::prelude::shape::Cube { size: CRATE_SIZE })),
+        material: materials.add(kind.colour().into()),
+        transform: Transform::from_translation(spot),
+        ..Default::default()
+    })
+    .insert_bundle(ColliderBundle {
+        // taller than the crate, so it reaches up to the hulls sailing over it
+        shape: ColliderShape::cuboid(CRATE_SIZE / 2.0, CRATE_SIZE, CRATE_SIZE / 2.0).into(),
+        position: spot.into(),
+        collider_type: ColliderType::Sensor.into(),
+        flags: ActiveEvents::INTERSECTION_EVENTS.into(),
+        ..Default::default()
+    })
+    .insert(Pickup { kind, spawned: now });
+}
+
+/// Bobs crates on the swell, and sinks any left too long
+pub fn pickup_bobbing(
+    mut commands: Commands,
+    mut pickups: Query<(Entity, &Pickup, &mut Transform)>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    for (entity, pickup, mut t) in pickups.iter_mut() {
+        let afloat = clock.elapsed - pickup.spawned;
+        if afloat > config.powerups.lifetime {
+            commands.entity(entity).despawn_recursive();
+        } else {
+            t.translation.y = (afloat * BOB_RATE * std::f64::consts::TAU).sin() as f32 * BOB_HEIGHT;
+        }
+    }
+}
+
+/// Hands out the power-up in each crate a player sails into
+#[allow(clippy::too_many_arguments)]
+pub fn pickup_collection(
+    mut commands: Commands,
+    pickups: Query<&Pickup>,
+    mut players: Query<(&mut Ship, &mut Buffs), With<Player>>,
+    mut intersection_events: EventReader<IntersectionEvent>,
+    asset_server: Res<AssetServer>,
+    audio: Res<Audio>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    // a crate touched by two ships at once only goes to one of them
+    let mut collected = HashSet::new();
+    for event in intersection_events.iter().filter(|event| event.intersecting) {
+        for (crate_handle, ship_handle) in [(event.collider1, event.collider2), (event.collider2, event.collider1)] {
+            let crate_entity = crate_handle.entity();
+            if collected.contains(&crate_entity) {
+                continue;
+            }
+            if let (Ok(pickup), Ok((mut ship, mut buffs))) = (
+                pickups.get(crate_entity), players.get_mut(ship_handle.entity())
+            ) {
+                match pickup.kind {
+                    PowerUp::Repair => {
+                        ship.health = (ship.health + config.powerups.repair).min(config.player_health);
+                    },
+                    kind => {
+                        buffs.expires.insert(kind, clock.elapsed + config.powerups.duration);
+                    }
+                }
+                collected.insert(crate_entity);
+                commands.entity(crate_entity).despawn_recursive();
+                audio.play(asset_server.load("sounds/laser.ogg"));
+            }
+        }
+    }
+}
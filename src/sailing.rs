@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+
+use std::f32::consts;
+
+use crate::GameClock;
+use crate::camera;
+use crate::config::GameConfig;
+
+// Fraction of full thrust against the angle between a ship's heading and the
+// way the wind blows, from running before it to heading straight into it
+const SAIL_POLAR: [(f32, f32); 5] = [
+    (0.0, 0.7),
+    (consts::FRAC_PI_4, 0.9),
+    (consts::FRAC_PI_2, 1.0),
+    (consts::FRAC_PI_4 * 3.0, 0.5),
+    (consts::PI, 0.1)
+];
+
+// Canvas set when backed, as a fraction of full sail
+const BACKED_CANVAS: f32 = 0.25;
+
+/// The wind blowing over the arena right now
+pub struct Wind {
+    /// Unit vector the way the wind blows
+    pub direction: Vec3,
+    pub strength: f32
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Wind { direction: Vec3::X, strength: 1.0 }
+    }
+}
+
+impl Wind {
+    /// Arrow pointing the way the wind blows on screen
+    pub fn arrow(&self) -> char {
+        const ARROWS: [char; 8] = ['→', '↗', '↑', '↖', '←', '↙', '↓', '↘'];
+        let angle = camera::screen_angle(self.direction);
+        let sector = (angle / consts::FRAC_PI_4).round().rem_euclid(8.0) as usize;
+        ARROWS[sector % 8]
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self.strength {
+            strength if strength < 0.3 => "calm",
+            strength if strength < 0.8 => "light",
+            strength if strength < 1.2 => "fresh",
+            _ => "strong"
+        }
+    }
+}
+
+/// How much sail a ship is asking for, stepped up and down by its captain
+#[derive(Clone, Copy, Eq, PartialEq, Debug)]
+pub enum SailState {
+    /// Sails backed against the wind, so the ship drifts astern
+    Backed,
+    /// Sails furled and the anchor down
+    Anchored,
+    Half,
+    Full
+}
+
+impl SailState {
+    /// Fraction of the canvas set for this state, negative when backed
+    fn canvas(&self) -> f32 {
+        match self {
+            SailState::Backed => -BACKED_CANVAS,
+            SailState::Anchored => 0.0,
+            SailState::Half => 0.5,
+            SailState::Full => 1.0
+        }
+    }
+
+    pub fn raised(&self) -> Self {
+        match self {
+            SailState::Backed => SailState::Anchored,
+            SailState::Anchored => SailState::Half,
+            SailState::Half | SailState::Full => SailState::Full
+        }
+    }
+
+    pub fn lowered(&self) -> Self {
+        match self {
+            SailState::Full => SailState::Half,
+            SailState::Half => SailState::Anchored,
+            SailState::Anchored | SailState::Backed => SailState::Backed
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            SailState::Backed => "backed",
+            SailState::Anchored => "anchored",
+            SailState::Half => "half",
+            SailState::Full => "full"
+        }
+    }
+}
+
+#[derive(Component)]
+pub struct Sails {
+    pub state: SailState,
+    /// Fraction of the canvas set, which follows the state as the crew work
+    /// the sails. Negative when backed.
+    pub set: f32
+}
+
+impl Sails {
+    pub fn new(state: SailState) -> Self {
+        Sails { state, set: state.canvas() }
+    }
+
+    /// Works the canvas towards what the state calls for, by at most `max_change`
+    fn trim(&mut self, max_change: f32) {
+        self.set += (self.state.canvas() - self.set).clamp(-max_change, max_change);
+    }
+
+    /// Fraction of full thrust the sails give on this heading, negative when
+    /// drifting astern
+    pub fn drive(&self, wind: &Wind, heading: Vec3) -> f32 {
+        if self.set >= 0.0 {
+            self.set * wind.strength * sail_efficiency(heading.angle_between(wind.direction))
+        } else {
+            // backed sails only slow the ship and push it astern, whatever the heading
+            self.set * wind.strength
+        }
+    }
+}
+
+/// Looks up the sail polar, for an angle in radians between the heading and
+/// the way the wind blows
+fn sail_efficiency(angle: f32) -> f32 {
+    let angle = angle.clamp(0.0, consts::PI);
+    SAIL_POLAR.windows(2)
+        .find(|pair| angle <= pair[1].0)
+        .map_or(SAIL_POLAR[SAIL_POLAR.len() - 1].1, |pair| {
+            let ((from_angle, from), (to_angle, to)) = (pair[0], pair[1]);
+            from + (to - from) * (angle - from_angle) / (to_angle - from_angle)
+        })
+}
+
+/// Swings the wind about its prevailing direction and blows it up and down
+/// in gusts, following the game clock so a paused game keeps the same wind
+pub fn wind_system(
+    mut wind: ResMut<Wind>,
+    clock: Res<GameClock>,
+    config: Res<GameConfig>
+) {
+    let wind_config = &config.wind;
+    let cycle = |period: f64| {
+        if period > 0.0 {
+            (clock.elapsed / period * std::f64::consts::TAU).sin() as f32
+        } else {
+            0.0
+        }
+    };
+    // two cycles out of step, so gusts don't come like clockwork
+    let gust = (cycle(wind_config.gust_period) + cycle(wind_config.gust_period * 1.618)) / 2.0;
+    let veer = cycle(wind_config.veer_period) * wind_config.veer;
+    wind.direction = Quat::from_rotation_y(wind_config.direction + veer) * Vec3::X;
+    wind.strength = (wind_config.strength + gust * wind_config.gust_strength).max(0.0);
+}
+
+pub fn sail_handling(
+    mut sails: Query<&mut Sails>,
+    time: Res<Time>,
+    config: Res<GameConfig>
+) {
+    for mut sails in sails.iter_mut() {
+        sails.trim(config.sail_trim_rate * time.delta_seconds());
+    }
+}
@@ -0,0 +1,256 @@
+use bevy::{prelude::*, app::Events};
+use serde::{Deserialize, Serialize};
+
+use std::{fs, path::PathBuf};
+
+use crate::{GameClock, HighScoreText, Player, Ship};
+use crate::config::GameConfig;
+use crate::input::{Action, PlayerActions};
+
+const HIGH_SCORE_COUNT: usize = 10;
+const NAME_LENGTH: usize = 3;
+
+/// Sent whenever enemies are killed, so points can be awarded
+pub struct KillEvent {
+    /// Ship that made the kills, if a ship did
+    pub shooter: Option<Entity>,
+    /// Kills made at once with the same weapon
+    pub count: u32
+}
+
+/// Sent when a single laser shot sinks more than one enemy
+pub struct MultiKill {
+    pub shooter: Entity,
+    pub count: u32
+}
+
+#[derive(Default)]
+pub struct Score {
+    pub points: u32,
+    /// Game clock reading when the round began
+    started: f64,
+    /// Whole seconds survived this round, each already paid out
+    survived: u32
+}
+
+pub fn score_setup(mut score: ResMut<Score>, clock: Res<GameClock>) {
+    *score = Score { started: clock.elapsed, ..Default::default() };
+}
+
+pub fn score_system(
+    mut score: ResMut<Score>,
+    mut kill_events: EventReader<KillEvent>,
+    mut multi_kill_events: EventReader<MultiKill>,
+    ships: Query<&Ship, With<Player>>,
+    config: Res<GameConfig>,
+    clock: Res<GameClock>
+) {
+    let scoring = config.scoring;
+    for kill in kill_events.iter() {
+        // only players score, not ships lost to ramming or the open sea
+        let shooter = match kill.shooter.and_then(|shooter| ships.get(shooter).ok()) {
+            Some(shooter) => shooter,
+            None => continue
+        };
+        let mut points = scoring.kill_points * kill.count;
+        if (shooter.health as f32) < config.player_health as f32 * scoring.low_health_fraction {
+            points += scoring.low_health_bonus * kill.count;
+        }
+        score.points += points;
+    }
+    for multi_kill in multi_kill_events.iter() {
+        score.points += scoring.multi_kill_bonus * (multi_kill.count - 1);
+    }
+    // survival points for each whole second
+    let survived = (clock.elapsed - score.started).max(0.0) as u32;
+    score.points += survived.saturating_sub(score.survived) * scoring.points_per_second;
+    score.survived = survived;
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct HighScore {
+    pub name: String,
+    pub points: u32
+}
+
+/// Best scores so far, kept in the user's data directory between runs
+#[derive(Default, Deserialize, Serialize)]
+pub struct HighScores {
+    entries: Vec<HighScore>
+}
+
+impl HighScores {
+    fn path() -> Option<PathBuf> {
+        dirs::data_dir().map(|dir| dir.join("yo-ho-ho").join("highscores.ron"))
+    }
+
+    pub fn load() -> Self {
+        let contents = match Self::path().and_then(|path| fs::read_to_string(path).ok()) {
+            Some(contents) => contents,
+            None => return HighScores::default()
+        };
+        ron::de::from_str(&contents).unwrap_or_else(|err| {
+            warn!("Ignoring unreadable high score table: {}", err);
+            HighScores::default()
+        })
+    }
+
+    fn save(&self) {
+        let path = match Self::path() {
+            Some(path) => path,
+            None => return
+        };
+        let result = ron::ser::to_string_pretty(self, Default::default())
+            .map_err(|err| err.to_string())
+            .and_then(|contents| {
+                if let Some(dir) = path.parent() {
+                    fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+                }
+                fs::write(&path, contents).map_err(|err| err.to_string())
+            });
+        if let Err(err) = result {
+            warn!("Couldn't save high scores to {}: {}", path.display(), err);
+        }
+    }
+
+    fn qualifies(&self, points: u32) -> bool {
+        points > 0 && (
+            self.entries.len() < HIGH_SCORE_COUNT ||
+            self.entries.iter().any(|entry| points > entry.points)
+        )
+    }
+
+    fn insert(&mut self, entry: HighScore) {
+        let position = self.entries.iter()
+            .position(|existing| entry.points > existing.points)
+            .unwrap_or(self.entries.len());
+        self.entries.insert(position, entry);
+        self.entries.truncate(HIGH_SCORE_COUNT);
+    }
+
+    pub fn table(&self) -> String {
+        let mut text = "High scores\n".to_string();
+        for (rank, entry) in self.entries.iter().enumerate() {
+            text += &format!("{:>2}. {} {:>7}\n", rank + 1, entry.name, entry.points);
+        }
+        text
+    }
+}
+
+/// Arcade style initials, picked letter by letter
+pub struct NameEntry {
+    pub active: bool,
+    letters: [u8; NAME_LENGTH],
+    cursor: usize,
+    /// Set by the first key pressed after the game ends, so keys still held
+    /// from play don't type into the name as they repeat
+    typing: bool
+}
+
+impl Default for NameEntry {
+    fn default() -> Self {
+        NameEntry {
+            active: false,
+            letters: [b'A'; NAME_LENGTH],
+            cursor: 0,
+            typing: false
+        }
+    }
+}
+
+impl NameEntry {
+    fn name(&self) -> String {
+        String::from_utf8_lossy(&self.letters).to_string()
+    }
+
+    fn cycle(&mut self, step: i8) {
+        let letter = &mut self.letters[self.cursor];
+        *letter = b'A' + ((*letter - b'A') as i8 + step).rem_euclid(26) as u8;
+    }
+
+    fn display(&self) -> String {
+        self.letters.iter().enumerate()
+            .map(|(i, letter)| {
+                if i == self.cursor {
+                    format!("[{}]", *letter as char)
+                } else {
+                    format!(" {} ", *letter as char)
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn high_score_setup(
+    score: Res<Score>,
+    high_scores: Res<HighScores>,
+    mut name_entry: ResMut<NameEntry>,
+    mut characters: ResMut<Events<ReceivedCharacter>>
+) {
+    // anything typed during play isn't meant for the name
+    characters.clear();
+    *name_entry = NameEntry {
+        active: high_scores.qualifies(score.points),
+        ..Default::default()
+    };
+}
+
+pub fn name_entry_handler(
+    player_actions: Res<PlayerActions>,
+    mut characters: EventReader<ReceivedCharacter>,
+    keys: Res<Input<KeyCode>>,
+    mut name_entry: ResMut<NameEntry>,
+    mut high_scores: ResMut<HighScores>,
+    score: Res<Score>
+) {
+    if !name_entry.active {
+        return;
+    }
+    if keys.get_just_pressed().next().is_some() {
+        name_entry.typing = true;
+    }
+    // typed letters fill in the current slot and move on
+    for character in characters.iter() {
+        if name_entry.typing && character.char.is_ascii_alphabetic() {
+            let cursor = name_entry.cursor;
+            name_entry.letters[cursor] = character.char.to_ascii_uppercase() as u8;
+            name_entry.cursor = (cursor + 1).min(NAME_LENGTH - 1);
+        }
+    }
+    if player_actions.any_just_pressed(Action::MenuUp) {
+        name_entry.cycle(1);
+    }
+    if player_actions.any_just_pressed(Action::MenuDown) {
+        name_entry.cycle(-1);
+    }
+    if player_actions.any_just_pressed(Action::MenuLeft) {
+        name_entry.cursor = name_entry.cursor.saturating_sub(1);
+    }
+    if player_actions.any_just_pressed(Action::MenuRight) {
+        name_entry.cursor = (name_entry.cursor + 1).min(NAME_LENGTH - 1);
+    }
+    if player_actions.any_just_pressed(Action::Confirm) {
+        name_entry.active = false;
+        high_scores.insert(HighScore { name: name_entry.name(), points: score.points });
+        high_scores.save();
+    }
+}
+
+pub fn high_score_display(
+    mut text_query: Query<&mut Text, With<HighScoreText>>,
+    name_entry: Res<NameEntry>,
+    high_scores: Res<HighScores>,
+    score: Res<Score>
+) {
+    if let Some(mut text) = text_query.iter_mut().next() {
+        text.sections[0].value =
+            if name_entry.active {
+                format!(
+                    "New high score: {}\nEnter your name\n{}\n\n{}",
+                    score.points, name_entry.display(), high_scores.table()
+                )
+            } else {
+                format!("Score: {}\n\n{}", score.points, high_scores.table())
+            };
+    }
+}
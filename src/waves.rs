@@ -0,0 +1,160 @@
+use bevy::{
+    prelude::*, reflect::TypeUuid
+};
+use serde::Deserialize;
+
+use crate::{EnemyCounter, GameClock, Spawner};
+use crate::ai::Formation;
+use crate::config::ConfigFile;
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Wave {
+    pub enemy_count: i32,
+    /// Most enemies alive at once
+    pub max_enemies: usize,
+    /// Range of seconds a spawner waits between enemies, as (min, max)
+    pub spawn_interval: (f64, f64),
+    /// (x, z) positions enemies sail in from
+    pub spawners: Vec<(f32, f32)>,
+    /// Archetype names with the relative chance of each being spawned
+    pub ships: Vec<(String, f32)>,
+    /// How the fleet lines up behind its flagship, if at all
+    #[serde(default)]
+    pub formation: Option<Formation>
+}
+
+/// The waves making up a round, in order
+#[derive(Clone, Debug, Deserialize, TypeUuid)]
+#[uuid = "d4c6ea45-7377-462f-80a7-0884b4ddc9fb"]
+pub struct WaveList {
+    pub waves: Vec<Wave>,
+    /// Seconds of calm between one wave being cleared and the next arriving
+    pub intermission: f64,
+    /// Added to the difficulty multiplier for each wave after the first
+    pub difficulty_step: f32,
+    /// Keep going after the final wave, repeating it with more enemies each time
+    pub endless: bool,
+    /// Extra enemies per repeat of the final wave in endless mode
+    pub endless_extra_enemies: i32
+}
+
+impl ConfigFile for WaveList {
+    const PATH: &'static str = "config/default.waves.ron";
+    const EXTENSION: &'static str = "waves.ron";
+}
+
+impl WaveList {
+    /// The wave with the given index, extending past the last wave in endless mode
+    pub fn wave(&self, number: usize) -> Option<Wave> {
+        let last = self.waves.len().checked_sub(1)?;
+        if number <= last {
+            Some(self.waves[number].clone())
+        } else if self.endless {
+            let mut wave = self.waves[last].clone();
+            wave.enemy_count += (number - last) as i32 * self.endless_extra_enemies;
+            Some(wave)
+        } else {
+            None
+        }
+    }
+
+    pub fn difficulty(&self, number: usize) -> f32 {
+        1.0 + self.difficulty_step * number as f32
+    }
+}
+
+pub struct WaveState {
+    /// Index of the current wave
+    pub number: usize,
+    pub wave: Option<Wave>,
+    /// Multiplier on enemy strength, growing with each wave
+    pub difficulty: f32,
+    /// When the calm before the next wave ends, if between waves
+    pub intermission_ends: Option<f64>,
+    /// Set once the final wave has been cleared
+    pub complete: bool
+}
+
+impl Default for WaveState {
+    fn default() -> Self {
+        WaveState {
+            number: 0,
+            wave: None,
+            difficulty: 1.0,
+            intermission_ends: None,
+            complete: false
+        }
+    }
+}
+
+fn start_wave(
+    commands: &mut Commands,
+    waves: &WaveList,
+    number: usize,
+    wave_state: &mut WaveState,
+    enemy_counter: &mut EnemyCounter,
+    spawners: &Query<Entity, With<Spawner>>
+) {
+    let wave = waves.wave(number);
+    if let Some(wave) = &wave {
+        enemy_counter.total = wave.enemy_count;
+        enemy_counter.to_spawn = wave.enemy_count;
+        enemy_counter.dead = 0;
+        // each wave brings its own spawn points
+        for spawner in spawners.iter() {
+            commands.entity(spawner).despawn_recursive();
+        }
+        for (x, z) in wave.spawners.iter() {
+            commands.spawn_bundle(PbrBundle {
+                transform: Transform::from_translation(Vec3::new(*x, 0.0, *z)),
+                ..Default::default()
+            }).insert(Spawner { last_spawned: 0.0, until_next: 0.0} );
+        }
+    }
+    *wave_state = WaveState {
+        number,
+        wave,
+        difficulty: waves.difficulty(number),
+        intermission_ends: None,
+        complete: false
+    };
+}
+
+pub fn wave_setup(
+    mut commands: Commands,
+    waves: Res<WaveList>,
+    mut wave_state: ResMut<WaveState>,
+    mut enemy_counter: ResMut<EnemyCounter>,
+    spawners: Query<Entity, With<Spawner>>
+) {
+    start_wave(&mut commands, &waves, 0, &mut wave_state, &mut enemy_counter, &spawners);
+}
+
+pub fn wave_progression(
+    mut commands: Commands,
+    waves: Res<WaveList>,
+    mut wave_state: ResMut<WaveState>,
+    mut enemy_counter: ResMut<EnemyCounter>,
+    spawners: Query<Entity, With<Spawner>>,
+    clock: Res<GameClock>
+) {
+    if wave_state.complete || wave_state.wave.is_none() {
+        return;
+    }
+    let now = clock.elapsed;
+    match wave_state.intermission_ends {
+        Some(ends) if now >= ends => {
+            let next = wave_state.number + 1;
+            start_wave(&mut commands, &waves, next, &mut wave_state, &mut enemy_counter, &spawners);
+        },
+        Some(_) => (),
+        None if enemy_counter.dead >= enemy_counter.total => {
+            if waves.wave(wave_state.number + 1).is_some() {
+                wave_state.intermission_ends = Some(now + waves.intermission);
+            } else {
+                wave_state.complete = true;
+            }
+        },
+        None => ()
+    }
+}